@@ -0,0 +1,194 @@
+use crate::{
+    expression::{parse_pipeline, Pipeline},
+    lexer::{Location, Token, TokenKind},
+    parser::{LiquidState, ParseError},
+};
+
+#[derive(Debug, Clone)]
+pub enum Node {
+    Text(String),
+    /// `{{ expression | filter: argument }}`
+    Output {
+        pipeline: Pipeline,
+        location: Location,
+    },
+    /// `{% name ... %}`, `markup` includes the name.
+    Tag {
+        name: String,
+        markup: String,
+        location: Location,
+    },
+    /// `{% name ... %} children {% endname %}` for every name in `Plugins::blocks`.
+    Block {
+        name: String,
+        markup: String,
+        location: Location,
+        children: Vec<Node>,
+    },
+}
+
+pub fn tag_name(markup: &str) -> &str {
+    markup.split_whitespace().next().unwrap_or_default()
+}
+
+struct OpenBlock {
+    name: String,
+    markup: String,
+    location: Location,
+    children: Vec<Node>,
+}
+
+/// Nests the tokens of a template into blocks.
+pub fn build(state: &LiquidState, tokens: Vec<Token>) -> Result<Vec<Node>, ParseError> {
+    let mut root = Vec::new();
+    let mut open: Vec<OpenBlock> = Vec::new();
+
+    for token in expand_liquid_tags(tokens) {
+        let node = match token.kind {
+            TokenKind::Text => Node::Text(token.markup.to_string()),
+            TokenKind::Output => match parse_pipeline(token.markup) {
+                Ok(pipeline) => Node::Output {
+                    pipeline,
+                    location: token.location,
+                },
+                Err(e) => return Err(e.add_stack(state, &token.location)),
+            },
+            TokenKind::Tag => {
+                let name = tag_name(token.markup);
+
+                if state.plugins.blocks.contains_key(name) {
+                    open.push(OpenBlock {
+                        name: name.to_string(),
+                        markup: token.markup.to_string(),
+                        location: token.location,
+                        children: Vec::new(),
+                    });
+                    continue;
+                }
+
+                let ended = name
+                    .strip_prefix("end")
+                    .filter(|ended| state.plugins.blocks.contains_key(*ended));
+
+                match ended {
+                    Some(ended) => {
+                        let block = match open.pop() {
+                            Some(block) if block.name == ended => block,
+                            Some(block) => {
+                                return Err(ParseError::new_at(
+                                    format!("Expected `end{}`, found `{name}`.", block.name),
+                                    state,
+                                    &token.location,
+                                ));
+                            }
+                            None => {
+                                return Err(ParseError::new_at(
+                                    format!("`{name}` has no opening `{ended}`."),
+                                    state,
+                                    &token.location,
+                                ));
+                            }
+                        };
+
+                        Node::Block {
+                            name: block.name,
+                            markup: block.markup,
+                            location: block.location,
+                            children: block.children,
+                        }
+                    }
+                    None => Node::Tag {
+                        name: name.to_string(),
+                        markup: token.markup.to_string(),
+                        location: token.location,
+                    },
+                }
+            }
+        };
+
+        match open.last_mut() {
+            Some(block) => block.children.push(node),
+            None => root.push(node),
+        }
+    }
+
+    if let Some(block) = open.pop() {
+        return Err(ParseError::new_at(
+            format!("`{}` is never closed with `end{}`.", block.name, block.name),
+            state,
+            &block.location,
+        ));
+    }
+
+    Ok(root)
+}
+
+const LIQUID: &str = "liquid";
+
+/// Expands every `{% liquid %}` tag into one tag token per line. Lines
+/// starting with `#` and `comment` ... `endcomment` lines are dropped.
+fn expand_liquid_tags(tokens: Vec<Token>) -> Vec<Token> {
+    let mut expanded = Vec::with_capacity(tokens.len());
+
+    for token in tokens {
+        if token.kind != TokenKind::Tag || tag_name(token.markup) != LIQUID {
+            expanded.push(token);
+            continue;
+        }
+
+        let markup_start = token.location.source.find(token.markup).unwrap_or(0);
+        let first_line =
+            token.location.line + token.location.source[..markup_start].matches('\n').count();
+
+        let mut comment_depth = 0;
+
+        for (i, line) in token.markup[LIQUID.len()..].split('\n').enumerate() {
+            let statement = line.trim();
+
+            match tag_name(statement) {
+                "comment" => comment_depth += 1,
+                "endcomment" if comment_depth > 0 => comment_depth -= 1,
+                _ if comment_depth > 0 || statement.is_empty() || statement.starts_with('#') => (),
+                _ => expanded.push(Token {
+                    kind: TokenKind::Tag,
+                    markup: statement,
+                    location: Location {
+                        line: first_line + i,
+                        column: match i {
+                            0 => token.location.column,
+                            _ => line.len() - line.trim_start().len() + 1,
+                        },
+                        source: statement.to_string(),
+                    },
+                }),
+            }
+        }
+    }
+
+    expanded
+}
+
+/// Splits the children of a block on its top level `separators` tags,
+/// e.g. `else` for `for` or `elsif`/`else` for `if`.
+///
+/// The first branch has an empty tag, every following branch carries the
+/// markup of the separator tag that opened it.
+pub fn split_block<'n>(nodes: &'n [Node], separators: &[&str]) -> Vec<(&'n str, &'n [Node])> {
+    let mut branches = Vec::new();
+
+    let mut branch_tag = "";
+    let mut branch_start = 0;
+
+    for (i, node) in nodes.iter().enumerate() {
+        if let Node::Tag { name, markup, .. } = node {
+            if separators.contains(&name.as_str()) {
+                branches.push((branch_tag, &nodes[branch_start..i]));
+                branch_tag = markup;
+                branch_start = i + 1;
+            }
+        }
+    }
+
+    branches.push((branch_tag, &nodes[branch_start..]));
+    branches
+}
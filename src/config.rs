@@ -2,6 +2,15 @@
 pub struct Config {
     pub output: String,
     pub theme: String,
+    /// Time zone of dates without an offset, `America/New_York`, `+01:00`,
+    /// `-0500` or `UTC`.
+    pub timezone: Option<String>,
+    /// Extensions of the templates whose outputs are HTML escaped, e.g. `[html, xml]`.
+    #[serde(default)]
+    pub autoescape: Vec<String>,
+    /// What an undefined variable or filter does, `lenient` by default.
+    #[serde(default)]
+    pub undefined: UndefinedMode,
 
     pub debug: Option<DebugConfig>,
 
@@ -10,20 +19,65 @@ pub struct Config {
 }
 
 impl Config {
-    pub fn new<P: AsRef<Path>>(path: P) -> Self {
-        let config: Config = serde_yaml::from_str(&fs::read_to_string(&path).exit(path)).unwrap();
+    /// Reads the config at `path`, its warnings are added to `report`.
+    pub fn new<P: AsRef<Path>>(path: P, report: &BuildReport) -> Result<Self, Error> {
+        let source = fs::read_to_string(&path).with_path(&path)?;
+        let path = path.as_ref().to_string_lossy();
+
+        let config: Config = match serde_yaml::from_str(&source) {
+            Ok(config) => config,
+            Err(e) => return Err(Error::Config(e.to_string())),
+        };
 
         if let Some(debug) = &config.debug {
             if let Value::Mapping(map) = &debug.other {
                 if !map.is_empty() {
-                    println!("Warning: unused value");
-                    println!("{map:?}");
+                    report.warning(Stage::Config, &path, &format!("unused value {map:?}"));
                 }
             }
         }
 
-        config
+        if let Some(timezone) = &config.timezone {
+            if Zone::parse(timezone).is_none() {
+                report.warning(
+                    Stage::Config,
+                    &path,
+                    &format!(
+                        "timezone {timezone:?} is not a time zone like \"Europe/Berlin\" \
+                         or an offset like \"+01:00\", using UTC"
+                    ),
+                );
+            }
+        }
+
+        Ok(config)
     }
+
+    /// Whether outputs of the template at `path` are HTML escaped.
+    pub fn autoescapes(&self, path: &str) -> bool {
+        match Path::new(path).extension() {
+            Some(extension) => self.autoescape.iter().any(|e| e.as_str() == extension),
+            None => false,
+        }
+    }
+
+    pub fn time_zone(&self) -> Zone {
+        self.timezone
+            .as_deref()
+            .and_then(Zone::parse)
+            .unwrap_or(Zone::UTC)
+    }
+}
+
+/// `lenient` renders undefined variables as nil and skips undefined filters
+/// with a warning, `strict` fails the page on either, after reporting every
+/// undefined variable of it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UndefinedMode {
+    #[default]
+    Lenient,
+    Strict,
 }
 
 #[derive(Debug, Deserialize)]
@@ -45,7 +99,12 @@ use std::{fs, path::Path};
 use serde::Deserialize;
 use serde_yaml::Value;
 
-use crate::helper::IoError;
+use crate::{
+    date::Zone,
+    helper::WithPath,
+    report::{BuildReport, Stage},
+    Error,
+};
 
 fn def_port() -> i32 {
     3000
@@ -0,0 +1,459 @@
+//! Dates of front matter and the `date` filters.
+
+use time::{Date, Duration, Month, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset};
+use time_tz::{
+    timezones, Offset, OffsetDateTimeExt, OffsetResult, PrimitiveDateTimeExt, TimeZone, Tz,
+};
+
+/// The site time zone, an IANA name like `America/New_York` that follows its
+/// daylight saving time or a fixed offset.
+#[derive(Debug, Clone, Copy)]
+pub enum Zone {
+    Offset(UtcOffset),
+    Named(&'static Tz),
+}
+
+impl Zone {
+    pub const UTC: Zone = Zone::Offset(UtcOffset::UTC);
+
+    /// `Europe/Berlin` or an offset, see [`parse_offset`].
+    pub fn parse(text: &str) -> Option<Self> {
+        match timezones::get_by_name(text.trim()) {
+            Some(tz) => Some(Zone::Named(tz)),
+            None => parse_offset(text).map(Zone::Offset),
+        }
+    }
+
+    /// The local `date_time` in this zone. A time repeated by a daylight
+    /// saving change is the first one, a skipped time uses the offset from
+    /// before the change, e.g. `02:30` becomes `03:30` on a spring forward.
+    pub fn assume(self, date_time: PrimitiveDateTime) -> OffsetDateTime {
+        let tz = match self {
+            Zone::Offset(offset) => return date_time.assume_offset(offset),
+            Zone::Named(tz) => tz,
+        };
+
+        match date_time.assume_timezone(tz) {
+            OffsetResult::Some(date) | OffsetResult::Ambiguous(date, _) => date,
+            OffsetResult::None => {
+                let before = tz.get_offset_utc(&(date_time - Duration::DAY).assume_utc());
+                date_time.assume_offset(before.to_utc()).to_timezone(tz)
+            }
+        }
+    }
+
+    /// `date` as the local time of this zone.
+    pub fn convert(self, date: OffsetDateTime) -> OffsetDateTime {
+        match self {
+            Zone::Offset(offset) => date.to_offset(offset),
+            Zone::Named(tz) => date.to_timezone(tz),
+        }
+    }
+}
+
+/// `UTC`, `Z`, `+01:00`, `+0100` or `+01`.
+pub fn parse_offset(text: &str) -> Option<UtcOffset> {
+    let text = text.trim();
+
+    if text.eq_ignore_ascii_case("utc") || text.eq_ignore_ascii_case("z") {
+        return Some(UtcOffset::UTC);
+    }
+
+    let sign = match text.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+
+    let digits: String = text[1..].chars().filter(|c| *c != ':').collect();
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let (hours, minutes) = match digits.len() {
+        1 | 2 => (digits.parse::<i8>().ok()?, 0),
+        4 => (digits[..2].parse().ok()?, digits[2..].parse().ok()?),
+        _ => return None,
+    };
+
+    UtcOffset::from_hms(sign * hours, sign * minutes, 0).ok()
+}
+
+/// Parses a YAML timestamp or an ISO 8601 date, e.g. `2024-03-01`,
+/// `2024-03-01 10:30:00 +01:00` or `2024-03-01T10:30:00Z`.
+///
+/// Dates without an offset are in the site time zone `zone`.
+pub fn parse_date(text: &str, zone: Zone) -> Option<OffsetDateTime> {
+    let text = text.trim();
+
+    if text.len() < 10 || !text.is_char_boundary(10) {
+        return None;
+    }
+
+    let (date, rest) = text.split_at(10);
+    let date = parse_calendar_date(date)?;
+
+    let rest = match rest.chars().next() {
+        None => return Some(zone.assume(PrimitiveDateTime::new(date, Time::MIDNIGHT))),
+        Some('T' | 't' | ' ') => rest[1..].trim_start(),
+        Some(_) => return None,
+    };
+
+    let time_end = rest
+        .find(|c: char| !(c.is_ascii_digit() || c == ':' || c == '.'))
+        .unwrap_or(rest.len());
+    let (time, offset) = rest.split_at(time_end);
+    let time = parse_time(time)?;
+
+    let date_time = PrimitiveDateTime::new(date, time);
+
+    match offset.trim() {
+        "" => Some(zone.assume(date_time)),
+        offset => Some(date_time.assume_offset(parse_offset(offset)?)),
+    }
+}
+
+fn parse_calendar_date(text: &str) -> Option<Date> {
+    let mut parts = text.split('-');
+
+    let year: i32 = number(parts.next()?, 4)?;
+    let month: u8 = number(parts.next()?, 2)?;
+    let day: u8 = number(parts.next()?, 2)?;
+
+    Date::from_calendar_date(year, Month::try_from(month).ok()?, day).ok()
+}
+
+/// `HH:MM`, `HH:MM:SS` or `HH:MM:SS.fraction`.
+fn parse_time(text: &str) -> Option<Time> {
+    let (text, fraction) = match text.split_once('.') {
+        Some((text, fraction)) => (text, fraction),
+        None => (text, ""),
+    };
+
+    let mut parts = text.split(':');
+
+    let hour: u8 = parts.next()?.parse().ok()?;
+    let minute: u8 = number(parts.next()?, 2)?;
+    let second: u8 = match parts.next() {
+        Some(second) => number(second, 2)?,
+        None => 0,
+    };
+
+    if parts.next().is_some() || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let nanosecond = match fraction.len() {
+        0 => 0,
+        _ => format!("{:0<9}", &fraction[..fraction.len().min(9)])
+            .parse()
+            .ok()?,
+    };
+
+    Time::from_hms_nano(hour, minute, second, nanosecond).ok()
+}
+
+fn number<T: std::str::FromStr>(text: &str, digits: usize) -> Option<T> {
+    match text.len() == digits && text.chars().all(|c| c.is_ascii_digit()) {
+        true => text.parse().ok(),
+        false => None,
+    }
+}
+
+/// Formats like Ruby's `Time#strftime`, the `-`, `_`, `0` and `^` flags
+/// and `%:z` included.
+pub fn strftime(date: &OffsetDateTime, format: &str) -> String {
+    let mut output = String::new();
+    let mut chars = format.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            output.push(c);
+            continue;
+        }
+
+        let mut flag = None;
+        if let Some(&f) = chars.peek() {
+            if matches!(f, '-' | '_' | '0' | '^') {
+                flag = Some(f);
+                chars.next();
+            }
+        }
+
+        let colon = chars.peek() == Some(&':');
+        if colon {
+            chars.next();
+        }
+
+        let directive = match chars.next() {
+            Some(directive) => directive,
+            None => {
+                output.push('%');
+                break;
+            }
+        };
+
+        let padded = |value: i64, width: usize, pad: char| match flag {
+            Some('-') => value.to_string(),
+            Some('_') => format!("{value:>width$}"),
+            Some('0') => format!("{value:0>width$}"),
+            _ if pad == ' ' => format!("{value:>width$}"),
+            _ => format!("{value:0>width$}"),
+        };
+        let text = |value: &str| match flag {
+            Some('^') => value.to_uppercase(),
+            _ => value.to_string(),
+        };
+
+        let hour12 = match date.hour() % 12 {
+            0 => 12,
+            hour => hour,
+        };
+
+        let formatted = match directive {
+            'Y' => date.year().to_string(),
+            'C' => padded(date.year() as i64 / 100, 2, '0'),
+            'y' => padded(date.year() as i64 % 100, 2, '0'),
+            'm' => padded(date.month() as i64, 2, '0'),
+            'B' => text(month_name(date.month())),
+            'b' | 'h' => text(&month_name(date.month())[..3]),
+            'd' => padded(date.day() as i64, 2, '0'),
+            'e' => padded(date.day() as i64, 2, ' '),
+            'j' => padded(date.ordinal() as i64, 3, '0'),
+            'H' => padded(date.hour() as i64, 2, '0'),
+            'k' => padded(date.hour() as i64, 2, ' '),
+            'I' => padded(hour12 as i64, 2, '0'),
+            'l' => padded(hour12 as i64, 2, ' '),
+            'M' => padded(date.minute() as i64, 2, '0'),
+            'S' => padded(date.second() as i64, 2, '0'),
+            'L' => format!("{:03}", date.millisecond()),
+            'N' => format!("{:09}", date.nanosecond()),
+            'p' => (if date.hour() < 12 { "AM" } else { "PM" }).to_string(),
+            'P' => (if date.hour() < 12 { "am" } else { "pm" }).to_string(),
+            'A' => text(weekday_name(date)),
+            'a' => text(&weekday_name(date)[..3]),
+            'u' => date.weekday().number_from_monday().to_string(),
+            'w' => date.weekday().number_days_from_sunday().to_string(),
+            'z' => offset(date.offset(), colon),
+            'Z' if date.offset().is_utc() => "UTC".to_string(),
+            'Z' => offset(date.offset(), true),
+            's' => date.unix_timestamp().to_string(),
+            'F' => strftime(date, "%Y-%m-%d"),
+            'D' | 'x' => strftime(date, "%m/%d/%y"),
+            'T' | 'X' => strftime(date, "%H:%M:%S"),
+            'R' => strftime(date, "%H:%M"),
+            'r' => strftime(date, "%I:%M:%S %p"),
+            'c' => strftime(date, "%a %b %e %H:%M:%S %Y"),
+            'n' => "\n".to_string(),
+            't' => "\t".to_string(),
+            '%' => "%".to_string(),
+            unknown => {
+                let mut unknown_directive = String::from("%");
+                unknown_directive.extend(flag);
+                if colon {
+                    unknown_directive.push(':');
+                }
+                unknown_directive.push(unknown);
+                unknown_directive
+            }
+        };
+
+        output.push_str(&formatted);
+    }
+
+    output
+}
+
+/// `2008-11-07T13:07:54-08:00`
+pub fn to_xmlschema(date: &OffsetDateTime) -> String {
+    strftime(date, "%Y-%m-%dT%H:%M:%S%:z")
+}
+
+/// `Fri, 07 Nov 2008 13:07:54 -0800`
+pub fn to_rfc822(date: &OffsetDateTime) -> String {
+    strftime(date, "%a, %d %b %Y %H:%M:%S %z")
+}
+
+/// How a date is output without a filter, like Ruby's `Time#to_s`.
+pub fn to_output(date: &OffsetDateTime) -> String {
+    strftime(date, "%Y-%m-%d %H:%M:%S %z")
+}
+
+fn offset(offset: UtcOffset, colon: bool) -> String {
+    let sign = if offset.is_negative() { '-' } else { '+' };
+    let (hours, minutes, _) = offset.as_hms();
+
+    match colon {
+        true => format!("{sign}{:02}:{:02}", hours.abs(), minutes.abs()),
+        false => format!("{sign}{:02}{:02}", hours.abs(), minutes.abs()),
+    }
+}
+
+fn month_name(month: Month) -> &'static str {
+    match month {
+        Month::January => "January",
+        Month::February => "February",
+        Month::March => "March",
+        Month::April => "April",
+        Month::May => "May",
+        Month::June => "June",
+        Month::July => "July",
+        Month::August => "August",
+        Month::September => "September",
+        Month::October => "October",
+        Month::November => "November",
+        Month::December => "December",
+    }
+}
+
+fn weekday_name(date: &OffsetDateTime) -> &'static str {
+    match date.weekday().number_days_from_sunday() {
+        0 => "Sunday",
+        1 => "Monday",
+        2 => "Tuesday",
+        3 => "Wednesday",
+        4 => "Thursday",
+        5 => "Friday",
+        _ => "Saturday",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hours(hours: i8) -> UtcOffset {
+        UtcOffset::from_hms(hours, 0, 0).unwrap()
+    }
+
+    fn date(text: &str) -> OffsetDateTime {
+        parse_date(text, Zone::UTC).unwrap()
+    }
+
+    fn new_york() -> Zone {
+        Zone::parse("America/New_York").unwrap()
+    }
+
+    #[test]
+    fn offsets() {
+        assert_eq!(parse_offset("UTC"), Some(UtcOffset::UTC));
+        assert_eq!(parse_offset("z"), Some(UtcOffset::UTC));
+        assert_eq!(parse_offset("+01:00"), Some(hours(1)));
+        assert_eq!(parse_offset("+0100"), Some(hours(1)));
+        assert_eq!(parse_offset("-05"), Some(hours(-5)));
+        assert_eq!(parse_offset("+05:30"), UtcOffset::from_hms(5, 30, 0).ok());
+        assert_eq!(parse_offset("+100"), None);
+        assert_eq!(parse_offset("01:00"), None);
+        assert_eq!(parse_offset("CET+1"), None);
+    }
+
+    #[test]
+    fn dates_without_an_offset_are_in_the_site_zone() {
+        let date = parse_date("2024-03-01", Zone::Offset(hours(2))).unwrap();
+
+        assert_eq!(strftime(&date, "%F %T %:z"), "2024-03-01 00:00:00 +02:00");
+    }
+
+    #[test]
+    fn dates_with_times_and_offsets() {
+        let cases = [
+            ("2024-03-01 10:30:00 +01:00", "2024-03-01 10:30:00 +01:00"),
+            ("2024-03-01T10:30:00Z", "2024-03-01 10:30:00 +00:00"),
+            ("2024-03-01t10:30:00-0500", "2024-03-01 10:30:00 -05:00"),
+            ("2024-03-01 10:30", "2024-03-01 10:30:00 +00:00"),
+            ("2024-03-01 9:05:07.25", "2024-03-01 09:05:07 +00:00"),
+        ];
+
+        for (text, expected) in cases {
+            assert_eq!(strftime(&date(text), "%F %T %:z"), expected, "{text}");
+        }
+
+        assert_eq!(date("2024-03-01 9:05:07.25").millisecond(), 250);
+    }
+
+    #[test]
+    fn invalid_dates() {
+        for text in [
+            "",
+            "not a date",
+            "2024-13-01",
+            "2024-02-30",
+            "2024-3-1",
+            "2024-03-01x",
+            "2024-03-01 25:00",
+            "2024-03-01 10:30 CET",
+        ] {
+            assert!(parse_date(text, Zone::UTC).is_none(), "{text}");
+        }
+    }
+
+    #[test]
+    fn named_zones_follow_daylight_saving_time() {
+        let at = |text: &str| strftime(&parse_date(text, new_york()).unwrap(), "%F %T %:z");
+
+        assert_eq!(at("2024-01-15 10:00"), "2024-01-15 10:00:00 -05:00");
+        assert_eq!(at("2024-07-15 10:00"), "2024-07-15 10:00:00 -04:00");
+        // Skipped by the spring forward and repeated by the fall back.
+        assert_eq!(at("2024-03-10 02:30"), "2024-03-10 03:30:00 -04:00");
+        assert_eq!(at("2024-11-03 01:30"), "2024-11-03 01:30:00 -04:00");
+
+        let utc = date("2024-07-15 14:00");
+        assert_eq!(
+            strftime(&new_york().convert(utc), "%F %T %:z"),
+            "2024-07-15 10:00:00 -04:00"
+        );
+        assert!(Zone::parse("Mars/Olympus").is_none());
+    }
+
+    #[test]
+    fn strftime_directives() {
+        let date = parse_date("2024-03-07 09:05:03 +01:00", Zone::UTC).unwrap();
+
+        let cases = [
+            ("%Y-%m-%d", "2024-03-07"),
+            ("%y %C %j", "24 20 067"),
+            ("%B %b %h", "March Mar Mar"),
+            ("%A %a %u %w", "Thursday Thu 4 4"),
+            ("%H %I %k %l", "09 09  9  9"),
+            ("%M:%S.%L", "05:03.000"),
+            ("%p %P", "AM am"),
+            ("%z %:z %Z", "+0100 +01:00 +01:00"),
+            ("%D %R %r", "03/07/24 09:05 09:05:03 AM"),
+            ("%c", "Thu Mar  7 09:05:03 2024"),
+            ("%s", "1709798703"),
+            ("100%%", "100%"),
+            ("%Q %", "%Q %"),
+        ];
+
+        for (format, expected) in cases {
+            assert_eq!(strftime(&date, format), expected, "{format}");
+        }
+    }
+
+    #[test]
+    fn strftime_flags() {
+        let date = parse_date("2024-03-07 09:05:03", Zone::UTC).unwrap();
+
+        let cases = [
+            ("%-d/%-m %-H:%-M", "7/3 9:5"),
+            ("%_d|%_m|%_H", " 7| 3| 9"),
+            ("%0e|%e", "07| 7"),
+            ("%^B %^a %^b", "MARCH THU MAR"),
+            ("%-y %-j", "24 67"),
+            ("%Z", "UTC"),
+        ];
+
+        for (format, expected) in cases {
+            assert_eq!(strftime(&date, format), expected, "{format}");
+        }
+    }
+
+    #[test]
+    fn outputs() {
+        let date = date("2008-11-07 13:07:54 -08:00");
+
+        assert_eq!(to_xmlschema(&date), "2008-11-07T13:07:54-08:00");
+        assert_eq!(to_rfc822(&date), "Fri, 07 Nov 2008 13:07:54 -0800");
+        assert_eq!(to_output(&date), "2008-11-07 13:07:54 -0800");
+    }
+}
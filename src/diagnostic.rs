@@ -0,0 +1,212 @@
+//! Rendering of build errors and warnings in the style of `rustc`.
+
+use std::{fmt, fs, io::IsTerminal};
+
+use crate::{
+    parser::{ParseError, StackInfo},
+    Error,
+};
+
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// Diagnostics are printed to stderr, they are coloured when it is a terminal
+/// and `NO_COLOR` is not set.
+pub fn colored() -> bool {
+    std::env::var_os("NO_COLOR").is_none() && std::io::stderr().is_terminal()
+}
+
+struct Painter {
+    colored: bool,
+}
+
+impl Painter {
+    fn paint(&self, style: &str, text: &str) -> String {
+        match self.colored {
+            true => format!("{style}{text}{RESET}"),
+            false => text.to_string(),
+        }
+    }
+}
+
+/// ```text
+/// error: Filter upcas does not exists.
+///  --> layouts/default.html:3:5
+///   |
+/// 3 | <h1>{{ title | upcas }}</h1>
+///   |     ^^^^^^^^^^^^^^^^^^^
+///   = note: in `{% include header.html %}` at site/index.html:1:1
+///   = help: did you mean `upcase`?
+/// ```
+pub fn write_error(f: &mut fmt::Formatter<'_>, error: &ParseError) -> fmt::Result {
+    let painter = Painter { colored: colored() };
+
+    writeln!(
+        f,
+        "{}{}",
+        painter.paint(RED, "error"),
+        painter.paint(BOLD, &format!(": {}", error.message))
+    )?;
+
+    let mut stack = error.stack.iter();
+    let gutter = error
+        .stack
+        .first()
+        .map(|at| at.line.to_string().len())
+        .unwrap_or(0);
+    let pad = " ".repeat(gutter);
+
+    if let Some(at) = stack.next() {
+        writeln!(
+            f,
+            "{pad}{} {}:{}:{}",
+            painter.paint(BLUE, "-->"),
+            at.path,
+            at.line,
+            at.column
+        )?;
+        write_snippet(f, &painter, at, &pad)?;
+    }
+
+    let bar = painter.paint(BLUE, "=");
+    for at in stack {
+        writeln!(
+            f,
+            "{pad} {bar} {}: in `{}` at {}:{}:{}",
+            painter.paint(BOLD, "note"),
+            first_line(&at.source),
+            at.path,
+            at.line,
+            at.column
+        )?;
+    }
+
+    for note in &error.notes {
+        writeln!(f, "{pad} {bar} {}: {note}", painter.paint(BOLD, "note"))?;
+    }
+
+    if let Some(help) = &error.help {
+        writeln!(f, "{pad} {bar} {}: {help}", painter.paint(BOLD, "help"))?;
+    }
+
+    Ok(())
+}
+
+/// The source line of `at` with the token underlined, nothing when the file
+/// can no longer be read.
+fn write_snippet(
+    f: &mut fmt::Formatter<'_>,
+    painter: &Painter,
+    at: &StackInfo,
+    pad: &str,
+) -> fmt::Result {
+    let line = match fs::read_to_string(&at.path).ok().and_then(|source| {
+        source
+            .lines()
+            .nth(at.line.saturating_sub(1))
+            .map(String::from)
+    }) {
+        Some(line) => line,
+        None => return Ok(()),
+    };
+
+    // Keep tabs so the caret lines up with the source line.
+    let indent: String = line
+        .chars()
+        .take(at.column.saturating_sub(1))
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let width = first_line(&at.source)
+        .chars()
+        .count()
+        .min(line.chars().count().saturating_sub(indent.chars().count()))
+        .max(1);
+
+    let bar = painter.paint(BLUE, "|");
+    writeln!(f, "{pad} {bar}")?;
+    writeln!(
+        f,
+        "{} {bar} {line}",
+        painter.paint(BLUE, &at.line.to_string())
+    )?;
+    writeln!(
+        f,
+        "{pad} {bar} {indent}{}",
+        painter.paint(RED, &"^".repeat(width))
+    )
+}
+
+/// Prints `error` with its source snippet when it has one.
+pub fn print_error(error: &Error) {
+    match error.parse_error() {
+        Some(parse_error) => eprintln!("{parse_error}"),
+        None => {
+            let painter = Painter { colored: colored() };
+            eprintln!(
+                "{}{}\n",
+                painter.paint(RED, "error"),
+                painter.paint(BOLD, &format!(": {error}"))
+            );
+        }
+    }
+}
+
+/// Prints a problem that does not stop the render.
+pub fn print_warning(message: &str, path: &str) {
+    let painter = Painter { colored: colored() };
+
+    eprintln!(
+        "{}{}\n {} {path}",
+        painter.paint(YELLOW, "warning"),
+        painter.paint(BOLD, &format!(": {message}")),
+        painter.paint(BLUE, "-->"),
+    );
+}
+
+fn first_line(text: &str) -> &str {
+    text.lines().next().unwrap_or_default()
+}
+
+/// The candidate closest to `name`, if it is close enough to be a typo.
+pub fn did_you_mean<'c>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'c String>,
+) -> Option<&'c str> {
+    let max_distance = (name.chars().count() / 3).max(1);
+
+    candidates
+        .into_iter()
+        .map(|candidate| (levenshtein(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by(|(a, a_name), (b, b_name)| a.cmp(b).then_with(|| a_name.cmp(b_name)))
+        .map(|(_, candidate)| candidate.as_str())
+}
+
+/// `did you mean `x`?` for the candidate closest to `name`.
+pub fn suggestion<'c>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'c String>,
+) -> Option<String> {
+    did_you_mean(name, candidates).map(|similar| format!("did you mean `{similar}`?"))
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+
+        previous = current;
+    }
+
+    previous[b.len()]
+}
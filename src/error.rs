@@ -0,0 +1,116 @@
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{parser::ParseError, report::Stage};
+
+/// Everything that can fail a build or a part of it.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing `path` failed.
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// `config.yaml` could not be parsed.
+    Config(String),
+    /// A page or one of its includes failed to render.
+    Template {
+        path: String,
+        error: ParseError,
+    },
+    FrontMatter {
+        path: String,
+        message: String,
+    },
+    /// The page at `path` failed to render inside one of its layouts.
+    Layout {
+        path: String,
+        error: ParseError,
+    },
+    /// An asset could not be copied to the output.
+    Asset {
+        path: PathBuf,
+        source: io::Error,
+    },
+}
+
+impl Error {
+    pub fn io<P: AsRef<Path>>(path: P, source: io::Error) -> Self {
+        Self::Io {
+            path: path.as_ref().to_path_buf(),
+            source,
+        }
+    }
+
+    /// Render error of the page at `path`, a `Layout` error when it happened
+    /// in a layout.
+    pub fn render(path: &str, error: ParseError) -> Self {
+        let path = path.to_string();
+
+        match error.stack.first() {
+            Some(at) if Stage::of_path(&at.path) == Stage::Layout => Self::Layout { path, error },
+            _ => Self::Template { path, error },
+        }
+    }
+
+    pub fn stage(&self) -> Stage {
+        match self {
+            Error::Io { path, .. } => Stage::of_path(&path.to_string_lossy()),
+            Error::Asset { .. } => Stage::Asset,
+            Error::Config(_) => Stage::Config,
+            Error::Template { .. } | Error::FrontMatter { .. } => Stage::Page,
+            Error::Layout { .. } => Stage::Layout,
+        }
+    }
+
+    /// The file the error is reported for.
+    pub fn path(&self) -> String {
+        match self {
+            Error::Io { path, .. } | Error::Asset { path, .. } => path.display().to_string(),
+            Error::Config(_) => "config.yaml".to_string(),
+            Error::Template { path, .. }
+            | Error::FrontMatter { path, .. }
+            | Error::Layout { path, .. } => path.clone(),
+        }
+    }
+
+    /// The render error with its source location, if the error has one.
+    pub fn parse_error(&self) -> Option<&ParseError> {
+        match self {
+            Error::Template { error, .. } | Error::Layout { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => {
+                write!(f, "Operation failed on {}. {source}", path.display())
+            }
+            Error::Config(message) => write!(f, "Invalid config. {message}"),
+            Error::Template { error, .. } | Error::Layout { error, .. } => {
+                f.write_str(&error.message)
+            }
+            Error::FrontMatter { path, message } => {
+                write!(f, "{path} has invalid front matter. {message}")
+            }
+            Error::Asset { path, source } => {
+                write!(f, "Could not copy {}. {source}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } | Error::Asset { source, .. } => Some(source),
+            Error::Template { error, .. } | Error::Layout { error, .. } => Some(error),
+            Error::Config(_) | Error::FrontMatter { .. } => None,
+        }
+    }
+}
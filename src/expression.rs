@@ -0,0 +1,612 @@
+use std::{fmt, sync::Arc};
+
+use crate::{
+    config::UndefinedMode,
+    diagnostic, filters,
+    liquid::Liquid,
+    parser::{LiquidResult, LiquidState, ParseError},
+    plugins::FilterArguments,
+};
+
+/// A value in a tag or output: a literal, a variable path or a range.
+#[derive(Debug, Clone)]
+pub enum Expression {
+    Literal(Liquid),
+    /// `page.tags[0]`, the first part is always a `Key`.
+    Variable(Vec<PathPart>),
+    /// `(1..5)`, inclusive on both ends.
+    Range(Box<Expression>, Box<Expression>),
+}
+
+#[derive(Debug, Clone)]
+pub enum PathPart {
+    Key(String),
+    Index(Expression),
+}
+
+#[derive(Debug, Clone)]
+pub struct Filter {
+    pub name: String,
+    pub positional: Vec<Expression>,
+    pub keyword: Vec<(String, Expression)>,
+}
+
+/// `expression | filter: argument, key: value | ...`
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    pub expression: Expression,
+    pub filters: Vec<Filter>,
+}
+
+pub fn parse_expression(markup: &str) -> Result<Expression, ParseError> {
+    let mut parser = Parser::new(markup)?;
+    let expression = parser.expression()?;
+    parser.finish()?;
+
+    Ok(expression)
+}
+
+pub fn parse_pipeline(markup: &str) -> Result<Pipeline, ParseError> {
+    let mut parser = Parser::new(markup)?;
+    let expression = parser.expression()?;
+
+    let mut filters = Vec::new();
+    while parser.eat(&Token::Pipe) {
+        filters.push(parser.filter()?);
+    }
+    parser.finish()?;
+
+    Ok(Pipeline {
+        expression,
+        filters,
+    })
+}
+
+/// `key: value, key: value`, the commas are optional as in
+/// `{% include card.html title: page.title url: item.url %}`.
+pub fn parse_named_arguments(markup: &str) -> Result<Vec<(String, Expression)>, ParseError> {
+    let mut parser = Parser::new(markup)?;
+    let mut arguments = Vec::new();
+
+    while parser.peek().is_some() {
+        let name = match parser.next() {
+            Some(Token::Identifier(name)) => name,
+            _ => {
+                parser.position -= 1;
+                return Err(parser.error("expected a parameter name"));
+            }
+        };
+
+        parser.expect(Token::Colon)?;
+        arguments.push((name, parser.expression()?));
+        parser.eat(&Token::Comma);
+    }
+
+    Ok(arguments)
+}
+
+impl Expression {
+    pub fn evaluate(&self, state: &LiquidState, object: &Liquid) -> LiquidResult {
+        match self {
+            Expression::Literal(value) => Ok(value.clone()),
+            Expression::Variable(path) => {
+                let mut temp = object.clone();
+
+                for (i, part) in path.iter().enumerate() {
+                    let found = match part {
+                        PathPart::Key(key) if i == 0 => state.find_variable(object, key)?,
+                        PathPart::Key(key) => state.find_step(&temp, key)?,
+                        PathPart::Index(index) => {
+                            let index = index.evaluate(state, object)?;
+                            match index.as_int() {
+                                Some(i) => state.find_index(&temp, i),
+                                None => state.find_step(&temp, &index.to_output())?,
+                            }
+                        }
+                    };
+
+                    temp = match (found, state.config.undefined) {
+                        (Some(value), _) => value,
+                        (None, UndefinedMode::Lenient) => return Ok(().into()),
+                        (None, UndefinedMode::Strict) => {
+                            let undefined = Expression::Variable(path[..=i].to_vec());
+                            state.undefined(&undefined.to_string());
+                            return Ok(().into());
+                        }
+                    };
+                }
+
+                Ok(temp)
+            }
+            Expression::Range(start, end) => {
+                let start = range_bound(state, object, start)?;
+                let end = range_bound(state, object, end)?;
+
+                let array: Vec<Liquid> = (start..=end).map(|i| i.into()).collect();
+                Ok(Arc::new(array).into())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expression::Literal(value) if value.is_string() => write!(f, "{value:?}"),
+            Expression::Literal(value) => write!(f, "{}", value.to_output()),
+            Expression::Variable(path) => {
+                for (i, part) in path.iter().enumerate() {
+                    match part {
+                        PathPart::Key(key) if i == 0 => write!(f, "{key}")?,
+                        PathPart::Key(key) => write!(f, ".{key}")?,
+                        PathPart::Index(index) => write!(f, "[{index}]")?,
+                    }
+                }
+                Ok(())
+            }
+            Expression::Range(start, end) => write!(f, "({start}..{end})"),
+        }
+    }
+}
+
+fn range_bound(
+    state: &LiquidState,
+    object: &Liquid,
+    bound: &Expression,
+) -> Result<i64, ParseError> {
+    let value = bound.evaluate(state, object)?;
+
+    if let Some(i) = value.as_int() {
+        return Ok(i);
+    }
+
+    if let Some(f) = value.as_float() {
+        return Ok(f as i64);
+    }
+
+    match value.to_output().trim().parse::<i64>() {
+        Ok(i) => Ok(i),
+        Err(_) => Err(format!("Invalid range bound {value:?}").into()),
+    }
+}
+
+impl Pipeline {
+    pub fn evaluate(&self, state: &LiquidState, object: &Liquid) -> LiquidResult {
+        let mut value = self.expression.evaluate(state, object)?;
+
+        for filter in &self.filters {
+            let filter_proc = match (
+                state.plugins.filters.get(&filter.name),
+                state.config.undefined,
+            ) {
+                (Some(filter_proc), _) => filter_proc,
+                (None, mode) => {
+                    let help = diagnostic::suggestion(&filter.name, state.plugins.filters.keys());
+
+                    if mode == UndefinedMode::Strict {
+                        let error =
+                            ParseError::new(format!("Filter {} does not exists.", filter.name));
+                        state.report_error(match help {
+                            Some(help) => error.with_help(help),
+                            None => error,
+                        });
+                        continue;
+                    }
+
+                    state.warn(&match help {
+                        Some(help) => format!(
+                            "Filter {} does not exists, it is skipped, {help}",
+                            filter.name
+                        ),
+                        None => format!("Filter {} does not exists, it is skipped.", filter.name),
+                    });
+                    continue;
+                }
+            };
+
+            let keeps_safe = value.is_safe()
+                && state.config.autoescapes(state.file_path)
+                && filters::SAFE_STRING_FILTERS.contains(&filter.name.as_str());
+            let prepare = |argument: Liquid| match keeps_safe {
+                true => filters::escape_argument(argument),
+                false => argument,
+            };
+
+            let mut arguments = FilterArguments::default();
+            for argument in &filter.positional {
+                arguments
+                    .positional
+                    .push(prepare(argument.evaluate(state, object)?));
+            }
+            for (name, argument) in &filter.keyword {
+                arguments
+                    .keyword
+                    .push((name.clone(), prepare(argument.evaluate(state, object)?)));
+            }
+
+            value = filter_proc(state, &value, &arguments)?;
+
+            if keeps_safe && value.as_string().is_some() {
+                value = Liquid::safe(value.to_output());
+            }
+        }
+
+        Ok(value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Identifier(String),
+    String(String),
+    Int(i64),
+    Float(f64),
+    Dot,
+    DotDot,
+    Pipe,
+    Colon,
+    Comma,
+    OpenParen,
+    CloseParen,
+    OpenBracket,
+    CloseBracket,
+}
+
+fn tokenize(markup: &str) -> Result<Vec<Token>, ParseError> {
+    let chars: Vec<char> = markup.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '"' | '\'' => {
+                let end = match chars[i + 1..].iter().position(|&q| q == c) {
+                    Some(end) => i + 1 + end,
+                    None => return Err(format!("Unterminated string in {markup:?}").into()),
+                };
+                tokens.push(Token::String(chars[i + 1..end].iter().collect()));
+                i = end + 1;
+            }
+            '.' if next == Some('.') => {
+                tokens.push(Token::DotDot);
+                i += 2;
+            }
+            '.' => {
+                tokens.push(Token::Dot);
+                i += 1;
+            }
+            '|' => {
+                tokens.push(Token::Pipe);
+                i += 1;
+            }
+            ':' => {
+                tokens.push(Token::Colon);
+                i += 1;
+            }
+            ',' => {
+                tokens.push(Token::Comma);
+                i += 1;
+            }
+            '(' => {
+                tokens.push(Token::OpenParen);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::CloseParen);
+                i += 1;
+            }
+            '[' => {
+                tokens.push(Token::OpenBracket);
+                i += 1;
+            }
+            ']' => {
+                tokens.push(Token::CloseBracket);
+                i += 1;
+            }
+            c if c.is_ascii_digit() || (c == '-' && next.is_some_and(|n| n.is_ascii_digit())) => {
+                let start = i;
+                i += 1;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+
+                // `1.5` is a float, `1..5` is a range.
+                let is_float = chars.get(i) == Some(&'.')
+                    && chars.get(i + 1).is_some_and(|n| n.is_ascii_digit());
+                if is_float {
+                    i += 1;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+
+                let number: String = chars[start..i].iter().collect();
+                tokens.push(match is_float {
+                    true => Token::Float(number.parse().expect("Checked while scanning.")),
+                    false => match number.parse() {
+                        Ok(n) => Token::Int(n),
+                        Err(e) => return Err(format!("Invalid number {number}. {e}").into()),
+                    },
+                });
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let start = i;
+                while i < chars.len()
+                    && (chars[i].is_alphanumeric() || matches!(chars[i], '_' | '-' | '?'))
+                {
+                    i += 1;
+                }
+                tokens.push(Token::Identifier(chars[start..i].iter().collect()));
+            }
+            c => return Err(format!("Unexpected character {c:?} in {markup:?}").into()),
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'m> {
+    markup: &'m str,
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl<'m> Parser<'m> {
+    fn new(markup: &'m str) -> Result<Self, ParseError> {
+        Ok(Self {
+            markup,
+            tokens: tokenize(markup)?,
+            position: 0,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.position += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), ParseError> {
+        match self.eat(&token) {
+            true => Ok(()),
+            false => Err(self.error(&format!("expected {token:?}"))),
+        }
+    }
+
+    fn error(&self, message: &str) -> ParseError {
+        match self.peek() {
+            Some(found) => format!(
+                "Invalid expression {:?}, {message}, found {found:?}.",
+                self.markup
+            ),
+            None => format!("Invalid expression {:?}, {message}.", self.markup),
+        }
+        .into()
+    }
+
+    fn finish(&self) -> Result<(), ParseError> {
+        match self.peek() {
+            Some(_) => Err(self.error("expected the end")),
+            None => Ok(()),
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expression, ParseError> {
+        match self.next() {
+            Some(Token::String(s)) => Ok(Expression::Literal(s.into())),
+            Some(Token::Int(i)) => Ok(Expression::Literal(i.into())),
+            Some(Token::Float(f)) => Ok(Expression::Literal(f.into())),
+            Some(Token::OpenParen) => {
+                let start = self.expression()?;
+                self.expect(Token::DotDot)?;
+                let end = self.expression()?;
+                self.expect(Token::CloseParen)?;
+
+                Ok(Expression::Range(Box::new(start), Box::new(end)))
+            }
+            Some(Token::Identifier(name)) => {
+                let is_path = matches!(self.peek(), Some(Token::Dot | Token::OpenBracket));
+
+                match name.as_str() {
+                    "true" if !is_path => Ok(Expression::Literal(true.into())),
+                    "false" if !is_path => Ok(Expression::Literal(false.into())),
+                    "nil" | "null" if !is_path => Ok(Expression::Literal(().into())),
+                    _ => self.variable(name),
+                }
+            }
+            _ => {
+                self.position = self.position.saturating_sub(1);
+                Err(self.error("expected a value"))
+            }
+        }
+    }
+
+    fn variable(&mut self, name: String) -> Result<Expression, ParseError> {
+        let mut path = vec![PathPart::Key(name)];
+
+        loop {
+            if self.eat(&Token::Dot) {
+                match self.next() {
+                    Some(Token::Identifier(key)) => path.push(PathPart::Key(key)),
+                    Some(Token::Int(i)) if i >= 0 => path.push(PathPart::Key(i.to_string())),
+                    _ => {
+                        self.position = self.position.saturating_sub(1);
+                        return Err(self.error("expected a key after `.`"));
+                    }
+                }
+                continue;
+            }
+
+            if self.eat(&Token::OpenBracket) {
+                path.push(PathPart::Index(self.expression()?));
+                self.expect(Token::CloseBracket)?;
+                continue;
+            }
+
+            return Ok(Expression::Variable(path));
+        }
+    }
+
+    fn filter(&mut self) -> Result<Filter, ParseError> {
+        let name = match self.next() {
+            Some(Token::Identifier(name)) => name,
+            _ => {
+                self.position = self.position.saturating_sub(1);
+                return Err(self.error("expected a filter name"));
+            }
+        };
+
+        let mut filter = Filter {
+            name,
+            positional: Vec::new(),
+            keyword: Vec::new(),
+        };
+
+        if !self.eat(&Token::Colon) {
+            return Ok(filter);
+        }
+
+        loop {
+            let keyword = match (self.peek(), self.tokens.get(self.position + 1)) {
+                (Some(Token::Identifier(name)), Some(Token::Colon)) => Some(name.clone()),
+                _ => None,
+            };
+
+            match keyword {
+                Some(name) => {
+                    self.position += 2;
+                    filter.keyword.push((name, self.expression()?));
+                }
+                None => filter.positional.push(self.expression()?),
+            }
+
+            if !self.eat(&Token::Comma) {
+                return Ok(filter);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn literal(markup: &str) -> Liquid {
+        match parse_expression(markup) {
+            Ok(Expression::Literal(value)) => value,
+            other => panic!("{markup:?} is not a literal, {other:?}"),
+        }
+    }
+
+    fn display(markup: &str) -> String {
+        parse_expression(markup).unwrap().to_string()
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(literal(r#""a b""#).as_string().unwrap(), "a b");
+        assert_eq!(literal(r#"'say "hi"'"#).as_string().unwrap(), r#"say "hi""#);
+        assert_eq!(literal("42").as_int(), Some(42));
+        assert_eq!(literal("-3").as_int(), Some(-3));
+        assert_eq!(literal("1.5").as_float(), Some(1.5));
+        assert_eq!(literal("true").as_bool(), Some(true));
+        assert_eq!(literal("false").as_bool(), Some(false));
+        assert!(literal("nil").is_nil());
+        assert!(literal("null").is_nil());
+    }
+
+    #[test]
+    fn variable_paths() {
+        assert_eq!(display("page.tags[0]"), "page.tags[0]");
+        assert_eq!(
+            display("site.data['key-name'].first"),
+            r#"site.data["key-name"].first"#
+        );
+        assert_eq!(display("items[page.index]"), "items[page.index]");
+        assert!(matches!(
+            parse_expression("true.size"),
+            Ok(Expression::Variable(_))
+        ));
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(display("(1..5)"), "(1..5)");
+        assert_eq!(display("( 1 .. page.count )"), "(1..page.count)");
+        assert_eq!(display("(-2..x.y)"), "(-2..x.y)");
+        assert!(parse_expression("(1..)").is_err());
+        assert!(parse_expression("(1..5").is_err());
+    }
+
+    #[test]
+    fn filters_and_arguments() {
+        let pipeline = parse_pipeline(
+            "title | truncate: 20, '...' | default: 'x', allow_false: true | upcase",
+        )
+        .unwrap();
+
+        assert_eq!(pipeline.expression.to_string(), "title");
+
+        let names: Vec<&str> = pipeline.filters.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["truncate", "default", "upcase"]);
+
+        let truncate: Vec<String> = pipeline.filters[0]
+            .positional
+            .iter()
+            .map(|argument| argument.to_string())
+            .collect();
+        assert_eq!(truncate, ["20", r#""...""#]);
+
+        let default = &pipeline.filters[1];
+        assert_eq!(default.positional.len(), 1);
+        assert_eq!(default.keyword.len(), 1);
+        assert_eq!(default.keyword[0].0, "allow_false");
+        assert_eq!(default.keyword[0].1.to_string(), "true");
+
+        assert!(pipeline.filters[2].positional.is_empty());
+    }
+
+    #[test]
+    fn named_arguments() {
+        let arguments = parse_named_arguments("title: page.title url: 'a.html', n: 2").unwrap();
+        let arguments: Vec<(String, String)> = arguments
+            .into_iter()
+            .map(|(name, value)| (name, value.to_string()))
+            .collect();
+
+        assert_eq!(
+            arguments,
+            [
+                ("title".to_string(), "page.title".to_string()),
+                ("url".to_string(), r#""a.html""#.to_string()),
+                ("n".to_string(), "2".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_pipelines() {
+        assert!(parse_pipeline("a |").is_err());
+        assert!(parse_pipeline("a | f:").is_err());
+        assert!(parse_pipeline("'open").is_err());
+        assert!(parse_pipeline("a b").is_err());
+        assert!(parse_pipeline("a.").is_err());
+        assert!(parse_pipeline("").is_err());
+    }
+}
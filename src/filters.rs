@@ -0,0 +1,905 @@
+//! The standard Liquid filters, registered in `plugins::LIQUID_FILTERS`.
+
+use std::{cmp::Ordering, sync::Arc};
+
+use time::OffsetDateTime;
+
+use crate::{
+    date,
+    liquid::{Liquid, Object},
+    parser::{LiquidResult, LiquidState, ParseError},
+    plugins::FilterArguments,
+};
+
+fn argument<'l>(
+    arguments: &'l FilterArguments,
+    index: usize,
+    filter: &str,
+) -> Result<&'l Liquid, ParseError> {
+    match arguments.get(index) {
+        Some(argument) => Ok(argument),
+        None => Err(format!("Filter {filter} expects at least {} arguments.", index + 1).into()),
+    }
+}
+
+fn string_argument(
+    arguments: &FilterArguments,
+    index: usize,
+    filter: &str,
+) -> Result<String, ParseError> {
+    Ok(argument(arguments, index, filter)?.to_output())
+}
+
+fn int_argument(
+    arguments: &FilterArguments,
+    index: usize,
+    filter: &str,
+    default: Option<i64>,
+) -> Result<i64, ParseError> {
+    let value = match (arguments.get(index), default) {
+        (Some(value), _) => value,
+        (None, Some(default)) => return Ok(default),
+        (None, None) => return Err(argument(arguments, index, filter).unwrap_err()),
+    };
+
+    if let Some(i) = value.as_int() {
+        return Ok(i);
+    }
+
+    if let Some(f) = value.as_float() {
+        return Ok(f as i64);
+    }
+
+    match value.to_output().trim().parse() {
+        Ok(i) => Ok(i),
+        Err(_) => Err(format!("Filter {filter} expects a number, found {value:?}.").into()),
+    }
+}
+
+pub fn pfn_upcase(_: &LiquidState, input: &Liquid, _: &FilterArguments) -> LiquidResult {
+    Ok(input.to_output().to_uppercase().into())
+}
+
+pub fn pfn_downcase(_: &LiquidState, input: &Liquid, _: &FilterArguments) -> LiquidResult {
+    Ok(input.to_output().to_lowercase().into())
+}
+
+pub fn pfn_capitalize(_: &LiquidState, input: &Liquid, _: &FilterArguments) -> LiquidResult {
+    let input = input.to_output();
+    let mut chars = input.chars();
+
+    let capitalized = match chars.next() {
+        Some(first) => first.to_uppercase().collect::<String>() + &chars.as_str().to_lowercase(),
+        None => input,
+    };
+
+    Ok(capitalized.into())
+}
+
+pub fn pfn_strip(_: &LiquidState, input: &Liquid, _: &FilterArguments) -> LiquidResult {
+    Ok(input.to_output().trim().into())
+}
+
+pub fn pfn_lstrip(_: &LiquidState, input: &Liquid, _: &FilterArguments) -> LiquidResult {
+    Ok(input.to_output().trim_start().into())
+}
+
+pub fn pfn_rstrip(_: &LiquidState, input: &Liquid, _: &FilterArguments) -> LiquidResult {
+    Ok(input.to_output().trim_end().into())
+}
+
+pub fn pfn_append(_: &LiquidState, input: &Liquid, arguments: &FilterArguments) -> LiquidResult {
+    Ok((input.to_output() + &string_argument(arguments, 0, "append")?).into())
+}
+
+pub fn pfn_prepend(_: &LiquidState, input: &Liquid, arguments: &FilterArguments) -> LiquidResult {
+    Ok((string_argument(arguments, 0, "prepend")? + &input.to_output()).into())
+}
+
+pub fn pfn_replace(_: &LiquidState, input: &Liquid, arguments: &FilterArguments) -> LiquidResult {
+    let from = string_argument(arguments, 0, "replace")?;
+    let to = string_argument(arguments, 1, "replace")?;
+
+    Ok(input.to_output().replace(&from, &to).into())
+}
+
+pub fn pfn_replace_first(
+    _: &LiquidState,
+    input: &Liquid,
+    arguments: &FilterArguments,
+) -> LiquidResult {
+    let from = string_argument(arguments, 0, "replace_first")?;
+    let to = string_argument(arguments, 1, "replace_first")?;
+
+    Ok(input.to_output().replacen(&from, &to, 1).into())
+}
+
+pub fn pfn_remove(_: &LiquidState, input: &Liquid, arguments: &FilterArguments) -> LiquidResult {
+    let from = string_argument(arguments, 0, "remove")?;
+
+    Ok(input.to_output().replace(&from, "").into())
+}
+
+pub fn pfn_remove_first(
+    _: &LiquidState,
+    input: &Liquid,
+    arguments: &FilterArguments,
+) -> LiquidResult {
+    let from = string_argument(arguments, 0, "remove_first")?;
+
+    Ok(input.to_output().replacen(&from, "", 1).into())
+}
+
+/// `"a,b" | split: ","`, a single space splits on any whitespace like Ruby does.
+pub fn pfn_split(_: &LiquidState, input: &Liquid, arguments: &FilterArguments) -> LiquidResult {
+    let input = input.to_output();
+    let separator = string_argument(arguments, 0, "split")?;
+
+    let mut parts: Vec<Liquid> = match separator.as_str() {
+        "" => input.chars().map(|c| c.to_string().into()).collect(),
+        " " => input.split_whitespace().map(|s| s.into()).collect(),
+        separator => input.split(separator).map(|s| s.into()).collect(),
+    };
+
+    while parts.last().is_some_and(|p| p.is_empty()) {
+        parts.pop();
+    }
+
+    Ok(Arc::new(parts).into())
+}
+
+/// `slice: offset, length`, negative offsets count from the end.
+pub fn pfn_slice(_: &LiquidState, input: &Liquid, arguments: &FilterArguments) -> LiquidResult {
+    let offset = int_argument(arguments, 0, "slice", None)?;
+    let length = int_argument(arguments, 1, "slice", Some(1))?.max(0) as usize;
+
+    fn range(len: usize, offset: i64, length: usize) -> Option<(usize, usize)> {
+        let start = match offset < 0 {
+            true => len.checked_sub(offset.unsigned_abs() as usize)?,
+            false => offset as usize,
+        };
+
+        if start > len {
+            return None;
+        }
+
+        Some((start, (start + length).min(len)))
+    }
+
+    if let Some(array) = input.as_array() {
+        return Ok(match range(array.len(), offset, length) {
+            Some((start, end)) => Arc::new(array[start..end].to_vec()).into(),
+            None => ().into(),
+        });
+    }
+
+    let chars: Vec<char> = input.to_output().chars().collect();
+    Ok(match range(chars.len(), offset, length) {
+        Some((start, end)) => chars[start..end].iter().collect::<String>().into(),
+        None => ().into(),
+    })
+}
+
+pub fn pfn_truncate(_: &LiquidState, input: &Liquid, arguments: &FilterArguments) -> LiquidResult {
+    let input = input.to_output();
+    let length = int_argument(arguments, 0, "truncate", Some(50))?.max(0) as usize;
+    let ellipsis = match arguments.get(1) {
+        Some(ellipsis) => ellipsis.to_output(),
+        None => "...".to_string(),
+    };
+
+    if input.chars().count() <= length {
+        return Ok(input.into());
+    }
+
+    let keep = length.saturating_sub(ellipsis.chars().count());
+    let truncated: String = input.chars().take(keep).collect();
+
+    Ok((truncated + &ellipsis).into())
+}
+
+pub fn pfn_truncatewords(
+    _: &LiquidState,
+    input: &Liquid,
+    arguments: &FilterArguments,
+) -> LiquidResult {
+    let input = input.to_output();
+    let words = int_argument(arguments, 0, "truncatewords", Some(15))?.max(1) as usize;
+    let ellipsis = match arguments.get(1) {
+        Some(ellipsis) => ellipsis.to_output(),
+        None => "...".to_string(),
+    };
+
+    let split: Vec<&str> = input.split_whitespace().collect();
+    if split.len() <= words {
+        return Ok(input.into());
+    }
+
+    Ok((split[..words].join(" ") + &ellipsis).into())
+}
+
+pub fn pfn_newline_to_br(_: &LiquidState, input: &Liquid, _: &FilterArguments) -> LiquidResult {
+    Ok(input
+        .to_output()
+        .replace("\r\n", "\n")
+        .replace('\n', "<br />\n")
+        .into())
+}
+
+pub fn pfn_strip_newlines(_: &LiquidState, input: &Liquid, _: &FilterArguments) -> LiquidResult {
+    Ok(input.to_output().replace(['\r', '\n'], "").into())
+}
+
+/// Form encoding like Ruby's `CGI.escape`, spaces become `+`.
+pub fn pfn_url_encode(_: &LiquidState, input: &Liquid, _: &FilterArguments) -> LiquidResult {
+    let mut encoded = String::new();
+
+    for byte in input.to_output().bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b' ' => encoded.push('+'),
+            byte => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+
+    Ok(encoded.into())
+}
+
+pub fn pfn_url_decode(_: &LiquidState, input: &Liquid, _: &FilterArguments) -> LiquidResult {
+    let input = input.to_output();
+    let bytes = input.as_bytes();
+
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match (bytes[i], hex) {
+            (b'+', _) => decoded.push(b' '),
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 2;
+            }
+            (byte, _) => decoded.push(byte),
+        }
+
+        i += 1;
+    }
+
+    Ok(String::from_utf8_lossy(&decoded).into_owned().into())
+}
+
+/// Elements of an array input, other values act as a single element array.
+fn elements(input: &Liquid) -> Vec<Liquid> {
+    match input.as_array() {
+        Some(array) => array.to_vec(),
+        None if input.is_nil() => Vec::new(),
+        None => vec![input.clone()],
+    }
+}
+
+/// `property` of an object element, nil when it is missing.
+fn property(state: &LiquidState, element: &Liquid, property: &str) -> Liquid {
+    if !element.is_object() {
+        return ().into();
+    }
+
+    state.get_key(element, property).unwrap_or_default()
+}
+
+fn property_argument(arguments: &FilterArguments, index: usize) -> Option<String> {
+    arguments.get(index).map(|p| p.to_output())
+}
+
+pub fn pfn_first(_: &LiquidState, input: &Liquid, _: &FilterArguments) -> LiquidResult {
+    if let Some(array) = input.as_array() {
+        return Ok(array.first().cloned().unwrap_or_default());
+    }
+
+    Ok(match input.as_string().and_then(|s| s.chars().next()) {
+        Some(c) => c.to_string().into(),
+        None => ().into(),
+    })
+}
+
+pub fn pfn_last(_: &LiquidState, input: &Liquid, _: &FilterArguments) -> LiquidResult {
+    if let Some(array) = input.as_array() {
+        return Ok(array.last().cloned().unwrap_or_default());
+    }
+
+    Ok(match input.as_string().and_then(|s| s.chars().last()) {
+        Some(c) => c.to_string().into(),
+        None => ().into(),
+    })
+}
+
+pub fn pfn_join(_: &LiquidState, input: &Liquid, arguments: &FilterArguments) -> LiquidResult {
+    let separator = match arguments.get(0) {
+        Some(separator) => separator.to_output(),
+        None => " ".to_string(),
+    };
+
+    let joined: Vec<String> = elements(input).iter().map(|e| e.to_output()).collect();
+    Ok(joined.join(&separator).into())
+}
+
+pub fn pfn_map(state: &LiquidState, input: &Liquid, arguments: &FilterArguments) -> LiquidResult {
+    let key = string_argument(arguments, 0, "map")?;
+
+    let mapped: Vec<Liquid> = elements(input)
+        .iter()
+        .map(|e| property(state, e, &key))
+        .collect();
+
+    Ok(Arc::new(mapped).into())
+}
+
+/// `where: "property", value`, without a value keeps truthy properties.
+pub fn pfn_where(state: &LiquidState, input: &Liquid, arguments: &FilterArguments) -> LiquidResult {
+    let key = string_argument(arguments, 0, "where")?;
+    let value = arguments.get(1);
+
+    let filtered: Vec<Liquid> = elements(input)
+        .into_iter()
+        .filter(|e| {
+            let found = property(state, e, &key);
+            match value {
+                Some(value) => found == *value,
+                None => found.is_truthy(),
+            }
+        })
+        .collect();
+
+    Ok(Arc::new(filtered).into())
+}
+
+/// Sorts with nil values last, incomparable values keep their order.
+fn sort_by_key(
+    state: &LiquidState,
+    input: &Liquid,
+    key: Option<String>,
+    mut compare: impl FnMut(&Liquid, &Liquid) -> Ordering,
+) -> Liquid {
+    let mut sorted: Vec<(Liquid, Liquid)> = elements(input)
+        .into_iter()
+        .map(|e| match &key {
+            Some(key) => (property(state, &e, key), e),
+            None => (e.clone(), e),
+        })
+        .collect();
+
+    sorted.sort_by(|(a, _), (b, _)| match (a.is_nil(), b.is_nil()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (false, false) => compare(a, b),
+    });
+
+    let sorted: Vec<Liquid> = sorted.into_iter().map(|(_, e)| e).collect();
+    Arc::new(sorted).into()
+}
+
+pub fn pfn_sort(state: &LiquidState, input: &Liquid, arguments: &FilterArguments) -> LiquidResult {
+    Ok(sort_by_key(
+        state,
+        input,
+        property_argument(arguments, 0),
+        |a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal),
+    ))
+}
+
+pub fn pfn_sort_natural(
+    state: &LiquidState,
+    input: &Liquid,
+    arguments: &FilterArguments,
+) -> LiquidResult {
+    Ok(sort_by_key(
+        state,
+        input,
+        property_argument(arguments, 0),
+        |a, b| {
+            a.to_output()
+                .to_lowercase()
+                .cmp(&b.to_output().to_lowercase())
+        },
+    ))
+}
+
+pub fn pfn_reverse(_: &LiquidState, input: &Liquid, _: &FilterArguments) -> LiquidResult {
+    let mut reversed = elements(input);
+    reversed.reverse();
+
+    Ok(Arc::new(reversed).into())
+}
+
+pub fn pfn_uniq(state: &LiquidState, input: &Liquid, arguments: &FilterArguments) -> LiquidResult {
+    let key = property_argument(arguments, 0);
+
+    let mut seen: Vec<Liquid> = Vec::new();
+    let mut unique = Vec::new();
+
+    for element in elements(input) {
+        let value = match &key {
+            Some(key) => property(state, &element, key),
+            None => element.clone(),
+        };
+
+        if !seen.contains(&value) {
+            seen.push(value);
+            unique.push(element);
+        }
+    }
+
+    Ok(Arc::new(unique).into())
+}
+
+pub fn pfn_compact(
+    state: &LiquidState,
+    input: &Liquid,
+    arguments: &FilterArguments,
+) -> LiquidResult {
+    let key = property_argument(arguments, 0);
+
+    let compacted: Vec<Liquid> = elements(input)
+        .into_iter()
+        .filter(|e| match &key {
+            Some(key) => !property(state, e, key).is_nil(),
+            None => !e.is_nil(),
+        })
+        .collect();
+
+    Ok(Arc::new(compacted).into())
+}
+
+pub fn pfn_concat(_: &LiquidState, input: &Liquid, arguments: &FilterArguments) -> LiquidResult {
+    let other = argument(arguments, 0, "concat")?;
+
+    if !other.is_array() {
+        return Err(format!("Filter concat expects an array, found {other:?}.").into());
+    }
+
+    let mut concatenated = elements(input);
+    concatenated.extend(elements(other));
+
+    Ok(Arc::new(concatenated).into())
+}
+
+pub fn pfn_size(_: &LiquidState, input: &Liquid, _: &FilterArguments) -> LiquidResult {
+    let size = match input.as_string() {
+        Some(s) => s.chars().count(),
+        None => input.len().unwrap_or_default(),
+    };
+
+    Ok((size as i64).into())
+}
+
+pub fn pfn_sum(state: &LiquidState, input: &Liquid, arguments: &FilterArguments) -> LiquidResult {
+    let key = property_argument(arguments, 0);
+
+    let mut int_sum: i64 = 0;
+    let mut float_sum: Option<f64> = None;
+
+    for element in elements(input) {
+        let value = match &key {
+            Some(key) => property(state, &element, key),
+            None => element,
+        };
+
+        if let Some(i) = value.as_int() {
+            int_sum += i;
+        } else if let Some(f) = value.as_float() {
+            *float_sum.get_or_insert(0.0) += f;
+        } else if let Some(s) = value.as_string() {
+            match (s.trim().parse::<i64>(), s.trim().parse::<f64>()) {
+                (Ok(i), _) => int_sum += i,
+                (_, Ok(f)) => *float_sum.get_or_insert(0.0) += f,
+                _ => (),
+            }
+        }
+    }
+
+    Ok(match float_sum {
+        Some(f) => (f + int_sum as f64).into(),
+        None => int_sum.into(),
+    })
+}
+
+/// Jekyll's `group_by`, an array of `{ name, items, size }` objects in first seen order.
+pub fn pfn_group_by(
+    state: &LiquidState,
+    input: &Liquid,
+    arguments: &FilterArguments,
+) -> LiquidResult {
+    let key = string_argument(arguments, 0, "group_by")?;
+
+    let mut groups: Vec<(Liquid, Vec<Liquid>)> = Vec::new();
+
+    for element in elements(input) {
+        let name = property(state, &element, &key);
+
+        match groups.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, items)) => items.push(element),
+            None => groups.push((name, vec![element])),
+        }
+    }
+
+    let groups: Vec<Liquid> = groups
+        .into_iter()
+        .map(|(name, items)| {
+            let mut group = Object::new();
+            group.insert("name".to_string(), name.to_output().into());
+            group.insert("size".to_string(), (items.len() as i64).into());
+            group.insert("items".to_string(), Arc::new(items).into());
+            Arc::new(group).into()
+        })
+        .collect();
+
+    Ok(Arc::new(groups).into())
+}
+
+/// A numeric filter operand, Liquid keeps integer math for two integers and
+/// promotes to floats otherwise.
+#[derive(Debug, Clone, Copy)]
+enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+impl From<Number> for Liquid {
+    fn from(value: Number) -> Self {
+        match value {
+            Number::Int(i) => i.into(),
+            Number::Float(f) => f.into(),
+        }
+    }
+}
+
+impl Number {
+    fn as_float(self) -> f64 {
+        match self {
+            Number::Int(i) => i as f64,
+            Number::Float(f) => f,
+        }
+    }
+}
+
+/// Numbers and numeric strings, anything else counts as `0`.
+fn number(value: &Liquid) -> Number {
+    if let Some(i) = value.as_int() {
+        return Number::Int(i);
+    }
+
+    if let Some(f) = value.as_float() {
+        return Number::Float(f);
+    }
+
+    let s = match value.as_string() {
+        Some(s) => s.trim().to_string(),
+        None => return Number::Int(0),
+    };
+
+    match (s.parse::<i64>(), s.parse::<f64>()) {
+        (Ok(i), _) => Number::Int(i),
+        (_, Ok(f)) => Number::Float(f),
+        _ => Number::Int(0),
+    }
+}
+
+fn number_argument(
+    arguments: &FilterArguments,
+    index: usize,
+    filter: &str,
+) -> Result<Number, ParseError> {
+    Ok(number(argument(arguments, index, filter)?))
+}
+
+/// Integer math while both sides are integers and the result fits.
+fn arithmetic(
+    left: Number,
+    right: Number,
+    int_op: fn(i64, i64) -> Option<i64>,
+    float_op: fn(f64, f64) -> f64,
+) -> Number {
+    if let (Number::Int(a), Number::Int(b)) = (left, right) {
+        if let Some(result) = int_op(a, b) {
+            return Number::Int(result);
+        }
+    }
+
+    Number::Float(float_op(left.as_float(), right.as_float()))
+}
+
+pub fn pfn_plus(_: &LiquidState, input: &Liquid, arguments: &FilterArguments) -> LiquidResult {
+    let operand = number_argument(arguments, 0, "plus")?;
+    Ok(arithmetic(number(input), operand, i64::checked_add, |a, b| a + b).into())
+}
+
+pub fn pfn_minus(_: &LiquidState, input: &Liquid, arguments: &FilterArguments) -> LiquidResult {
+    let operand = number_argument(arguments, 0, "minus")?;
+    Ok(arithmetic(number(input), operand, i64::checked_sub, |a, b| a - b).into())
+}
+
+pub fn pfn_times(_: &LiquidState, input: &Liquid, arguments: &FilterArguments) -> LiquidResult {
+    let operand = number_argument(arguments, 0, "times")?;
+    Ok(arithmetic(number(input), operand, i64::checked_mul, |a, b| a * b).into())
+}
+
+/// Integer division rounds down, like Ruby.
+pub fn pfn_divided_by(
+    _: &LiquidState,
+    input: &Liquid,
+    arguments: &FilterArguments,
+) -> LiquidResult {
+    let operand = number_argument(arguments, 0, "divided_by")?;
+
+    if let Number::Int(0) = operand {
+        return Err("Filter divided_by divided by 0.".to_string().into());
+    }
+
+    let int_division = |a: i64, b: i64| {
+        let quotient = a.checked_div(b)?;
+        match (a % b != 0) && ((a < 0) != (b < 0)) {
+            true => Some(quotient - 1),
+            false => Some(quotient),
+        }
+    };
+
+    Ok(arithmetic(number(input), operand, int_division, |a, b| a / b).into())
+}
+
+/// The result takes the sign of the divisor, like Ruby.
+pub fn pfn_modulo(_: &LiquidState, input: &Liquid, arguments: &FilterArguments) -> LiquidResult {
+    let operand = number_argument(arguments, 0, "modulo")?;
+
+    if let Number::Int(0) = operand {
+        return Err("Filter modulo divided by 0.".to_string().into());
+    }
+
+    let int_modulo = |a: i64, b: i64| {
+        a.checked_rem(b).map(|r| {
+            if r != 0 && (r < 0) != (b < 0) {
+                r + b
+            } else {
+                r
+            }
+        })
+    };
+    let float_modulo = |a: f64, b: f64| {
+        let r = a % b;
+        if r != 0.0 && (r < 0.0) != (b < 0.0) {
+            r + b
+        } else {
+            r
+        }
+    };
+
+    Ok(arithmetic(number(input), operand, int_modulo, float_modulo).into())
+}
+
+pub fn pfn_abs(_: &LiquidState, input: &Liquid, _: &FilterArguments) -> LiquidResult {
+    Ok(match number(input) {
+        Number::Int(i) => match i.checked_abs() {
+            Some(i) => i.into(),
+            None => (i as f64).abs().into(),
+        },
+        Number::Float(f) => f.abs().into(),
+    })
+}
+
+pub fn pfn_ceil(_: &LiquidState, input: &Liquid, _: &FilterArguments) -> LiquidResult {
+    Ok((number(input).as_float().ceil() as i64).into())
+}
+
+pub fn pfn_floor(_: &LiquidState, input: &Liquid, _: &FilterArguments) -> LiquidResult {
+    Ok((number(input).as_float().floor() as i64).into())
+}
+
+/// `round` gives an integer, `round: n` a float with `n` decimals.
+pub fn pfn_round(_: &LiquidState, input: &Liquid, arguments: &FilterArguments) -> LiquidResult {
+    let digits = int_argument(arguments, 0, "round", Some(0))?;
+
+    Ok(match (number(input), digits) {
+        (Number::Int(i), _) => i.into(),
+        (Number::Float(f), d) if d <= 0 => (f.round() as i64).into(),
+        (Number::Float(f), d) => {
+            let scale = 10f64.powi(d as i32);
+            ((f * scale).round() / scale).into()
+        }
+    })
+}
+
+pub fn pfn_at_least(_: &LiquidState, input: &Liquid, arguments: &FilterArguments) -> LiquidResult {
+    let input = number(input);
+    let minimum = number_argument(arguments, 0, "at_least")?;
+
+    Ok(match input.as_float() < minimum.as_float() {
+        true => minimum.into(),
+        false => input.into(),
+    })
+}
+
+pub fn pfn_at_most(_: &LiquidState, input: &Liquid, arguments: &FilterArguments) -> LiquidResult {
+    let input = number(input);
+    let maximum = number_argument(arguments, 0, "at_most")?;
+
+    Ok(match input.as_float() > maximum.as_float() {
+        true => maximum.into(),
+        false => input.into(),
+    })
+}
+
+/// Dates, timestamps and date strings, `"now"` and `"today"` included.
+fn date_input(state: &LiquidState, input: &Liquid) -> Option<OffsetDateTime> {
+    let zone = state.config.time_zone();
+
+    if let Some(date) = input.as_date() {
+        return Some(date);
+    }
+
+    let timestamp = match (input.as_int(), input.as_float(), input.as_string()) {
+        (Some(i), _, _) => i,
+        (_, Some(f), _) => f as i64,
+        (_, _, Some(s)) => match s.trim() {
+            "now" | "today" => return Some(zone.convert(OffsetDateTime::now_utc())),
+            s => match s.parse() {
+                Ok(timestamp) => timestamp,
+                Err(_) => return date::parse_date(s, zone),
+            },
+        },
+        _ => return None,
+    };
+
+    OffsetDateTime::from_unix_timestamp(timestamp)
+        .ok()
+        .map(|date| zone.convert(date))
+}
+
+/// `date: "%Y-%m-%d"`, values that are not dates pass through unchanged.
+pub fn pfn_date(state: &LiquidState, input: &Liquid, arguments: &FilterArguments) -> LiquidResult {
+    let format = match arguments.get(0) {
+        Some(format) if !format.is_nil() && !format.is_empty() => format.to_output(),
+        _ => return Ok(input.clone()),
+    };
+
+    Ok(match date_input(state, input) {
+        Some(date) => date::strftime(&date, &format).into(),
+        None => input.clone(),
+    })
+}
+
+pub fn pfn_date_to_xmlschema(
+    state: &LiquidState,
+    input: &Liquid,
+    _: &FilterArguments,
+) -> LiquidResult {
+    Ok(match date_input(state, input) {
+        Some(date) => date::to_xmlschema(&date).into(),
+        None => input.clone(),
+    })
+}
+
+pub fn pfn_date_to_rfc822(
+    state: &LiquidState,
+    input: &Liquid,
+    _: &FilterArguments,
+) -> LiquidResult {
+    Ok(match date_input(state, input) {
+        Some(date) => date::to_rfc822(&date).into(),
+        None => input.clone(),
+    })
+}
+
+/// String filters that only change their input, the result of a safe input
+/// stays safe. Their string arguments are escaped first so they can not add
+/// untrusted HTML to it.
+pub const SAFE_STRING_FILTERS: &[&str] = &[
+    "upcase",
+    "downcase",
+    "capitalize",
+    "strip",
+    "lstrip",
+    "rstrip",
+    "append",
+    "prepend",
+    "replace",
+    "replace_first",
+    "remove",
+    "remove_first",
+    "slice",
+    "truncate",
+    "truncatewords",
+    "newline_to_br",
+    "strip_newlines",
+];
+
+/// A string argument of a filter on a safe input, escaped unless it is safe.
+pub fn escape_argument(argument: Liquid) -> Liquid {
+    match argument.as_string() {
+        Some(text) if !argument.is_safe() => Liquid::safe(escape_html(text)),
+        _ => argument,
+    }
+}
+
+/// Escapes `&`, `<`, `>`, `"` and `'`, used by `escape` and auto-escaping.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Length of the entity `&amp;`, `&#39;` or `&#x27;` at the start of `text`.
+fn entity_len(text: &str) -> Option<usize> {
+    let end = text.find(';')?;
+    let name = &text[1..end];
+
+    let valid = match name.strip_prefix('#') {
+        Some(number) => match number.strip_prefix(['x', 'X']) {
+            Some(hex) => !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()),
+            None => !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()),
+        },
+        None => !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric()),
+    };
+
+    valid.then_some(end + 1)
+}
+
+pub fn pfn_escape(_: &LiquidState, input: &Liquid, _: &FilterArguments) -> LiquidResult {
+    if input.is_nil() {
+        return Ok(().into());
+    }
+
+    Ok(Liquid::safe(escape_html(&input.to_output())))
+}
+
+/// Like `escape` but keeps entities that are already escaped.
+pub fn pfn_escape_once(_: &LiquidState, input: &Liquid, _: &FilterArguments) -> LiquidResult {
+    if input.is_nil() {
+        return Ok(().into());
+    }
+
+    let text = input.to_output();
+    let mut escaped = String::with_capacity(text.len());
+    let mut rest = text.as_str();
+
+    while let Some(c) = rest.chars().next() {
+        let entity = match c {
+            '&' => entity_len(rest),
+            _ => None,
+        };
+
+        match entity {
+            Some(len) => {
+                escaped.push_str(&rest[..len]);
+                rest = &rest[len..];
+            }
+            None => {
+                escaped.push_str(&escape_html(&rest[..c.len_utf8()]));
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+
+    Ok(Liquid::safe(escaped))
+}
+
+/// Marks the value as trusted HTML, auto-escaping outputs it unchanged.
+pub fn pfn_raw(_: &LiquidState, input: &Liquid, _: &FilterArguments) -> LiquidResult {
+    Ok(Liquid::safe(input.to_output()))
+}
@@ -0,0 +1,488 @@
+use crate::{
+    ast::tag_name,
+    parser::{LiquidState, ParseError},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Text,
+    /// `{{ ... }}`
+    Output,
+    /// `{% ... %}`
+    Tag,
+}
+
+/// Where a token starts in its source file.
+#[derive(Debug, Clone, Default)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+    /// The token as written, delimiters included.
+    pub source: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Token<'s> {
+    pub kind: TokenKind,
+    /// Text for `Text` tokens, the trimmed inner markup otherwise.
+    pub markup: &'s str,
+    pub location: Location,
+}
+
+/// Splits a template into text, output and tag tokens.
+///
+/// Tags may span lines and contain quoted strings or nested `{{ }}` and
+/// whitespace-control markers (`{{-`, `-%}`, ...) are applied to the
+/// neighbouring text tokens here, so later stages never see them.
+///
+/// The bodies of `raw` and `comment` blocks are never scanned for tags: a
+/// `raw` body becomes a single text token and comments, `{% # inline %}` ones
+/// included, produce no tokens at all.
+pub fn tokenize<'s>(
+    state: &LiquidState,
+    source: &'s str,
+    skip_offset: usize,
+) -> Result<Vec<Token<'s>>, ParseError> {
+    let mut tokens: Vec<Token> = Vec::new();
+
+    let mut position = Position::new(state);
+    position.advance(&source[..skip_offset]);
+
+    let mut rest = skip_offset;
+    let mut trim_next_text = false;
+
+    while rest < source.len() {
+        let start = match find_opening(&source[rest..]) {
+            Some(i) => rest + i,
+            None => source.len(),
+        };
+
+        if start > rest {
+            let mut text = &source[rest..start];
+            if trim_next_text {
+                text = text.trim_start();
+            }
+
+            if !text.is_empty() {
+                tokens.push(Token {
+                    kind: TokenKind::Text,
+                    markup: text,
+                    location: position.location(""),
+                });
+            }
+
+            position.advance(&source[rest..start]);
+        }
+
+        if start == source.len() {
+            break;
+        }
+
+        let kind = match &source[start..start + 2] {
+            "{{" => TokenKind::Output,
+            _ => TokenKind::Tag,
+        };
+
+        let end = match find_closing(kind, &source[start + 2..]) {
+            Some(i) => start + 2 + i,
+            None => {
+                let location = position.location(&source[start..]);
+                let delimiter = if kind == TokenKind::Output {
+                    "}}"
+                } else {
+                    "%}"
+                };
+                return Err(ParseError::new_at(
+                    format!("Unterminated tag, expected `{delimiter}`."),
+                    state,
+                    &location,
+                ));
+            }
+        };
+
+        let token_source = &source[start..end];
+        let mut inner = &token_source[2..token_source.len() - 2];
+
+        let trim_before = inner.starts_with('-');
+        if trim_before {
+            inner = &inner[1..];
+        }
+
+        let trim_after = inner.ends_with('-');
+        if trim_after {
+            inner = &inner[..inner.len() - 1];
+        }
+
+        if trim_before {
+            if let Some(previous) = tokens.last_mut() {
+                if previous.kind == TokenKind::Text {
+                    previous.markup = previous.markup.trim_end();
+                    if previous.markup.is_empty() {
+                        tokens.pop();
+                    }
+                }
+            }
+        }
+
+        let markup = inner.trim();
+        let name = markup.split_whitespace().next().unwrap_or_default();
+
+        if kind == TokenKind::Tag && (name == "raw" || name == "comment") {
+            let location = position.location(token_source);
+
+            let verbatim = match find_verbatim_end(name, &source[end..]) {
+                Some(verbatim) => verbatim,
+                None => {
+                    return Err(ParseError::new_at(
+                        format!("`{name}` is never closed with `end{name}`."),
+                        state,
+                        &location,
+                    ))
+                }
+            };
+
+            let mut body = &source[end..end + verbatim.body_end];
+            if trim_after {
+                body = body.trim_start();
+            }
+            if verbatim.trim_before {
+                body = body.trim_end();
+            }
+
+            if name == "raw" && !body.is_empty() {
+                tokens.push(Token {
+                    kind: TokenKind::Text,
+                    markup: body,
+                    location,
+                });
+            }
+
+            position.advance(&source[start..end + verbatim.tag_end]);
+            trim_next_text = verbatim.trim_after;
+            rest = end + verbatim.tag_end;
+            continue;
+        }
+
+        if kind == TokenKind::Tag && markup.starts_with('#') {
+            position.advance(token_source);
+            trim_next_text = trim_after;
+            rest = end;
+            continue;
+        }
+
+        tokens.push(Token {
+            kind,
+            markup,
+            location: position.location(token_source),
+        });
+
+        position.advance(token_source);
+        trim_next_text = trim_after;
+        rest = end;
+    }
+
+    Ok(tokens)
+}
+
+fn find_opening(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+
+    (0..bytes.len().saturating_sub(1))
+        .find(|&i| bytes[i] == b'{' && (bytes[i + 1] == b'{' || bytes[i + 1] == b'%'))
+}
+
+/// Returns the index right after the closing delimiter.
+///
+/// Quotes are skipped over, except in `{% # inline %}` comments and in the
+/// `#` lines of a `{% liquid %}` tag where they are plain text.
+fn find_closing(kind: TokenKind, text: &str) -> Option<usize> {
+    let markup = text.strip_prefix('-').unwrap_or(text).trim_start();
+    if kind == TokenKind::Tag && markup.starts_with('#') {
+        return text.find("%}").map(|i| i + 2);
+    }
+
+    let liquid = kind == TokenKind::Tag && tag_name(markup) == "liquid";
+    // Where the next statement of a `{% liquid %}` tag starts.
+    let mut statement = liquid.then(|| text.len() - markup.len() + "liquid".len());
+
+    let bytes = text.as_bytes();
+
+    let mut quote = None;
+    let mut nested_outputs = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if statement == Some(i) {
+            let line = &text[i..];
+            let line = &line[..line.find('\n').unwrap_or(line.len())];
+
+            if line.trim_start().starts_with('#') {
+                if let Some(close) = line.find("%}") {
+                    return Some(i + close + 2);
+                }
+
+                i += line.len();
+                continue;
+            }
+        }
+
+        let c = bytes[i];
+        let next = bytes.get(i + 1).copied();
+
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => (),
+            None => match (c, next) {
+                (b'\n', _) if liquid => statement = Some(i + 1),
+                (b'"' | b'\'', _) => quote = Some(c),
+                (b'{', Some(b'{')) => {
+                    nested_outputs += 1;
+                    i += 1;
+                }
+                (b'}', Some(b'}')) if nested_outputs > 0 => {
+                    nested_outputs -= 1;
+                    i += 1;
+                }
+                (b'}', Some(b'}')) if kind == TokenKind::Output => return Some(i + 2),
+                (b'%', Some(b'}')) if kind == TokenKind::Tag => return Some(i + 2),
+                _ => (),
+            },
+        }
+
+        i += 1;
+    }
+
+    None
+}
+
+struct VerbatimEnd {
+    /// Where the body ends, at the closing tag.
+    body_end: usize,
+    /// Right after the closing tag.
+    tag_end: usize,
+    trim_before: bool,
+    trim_after: bool,
+}
+
+/// Finds the `end{name}` closing a `raw` or `comment` body, comments may nest.
+fn find_verbatim_end(name: &str, text: &str) -> Option<VerbatimEnd> {
+    let mut depth = 0;
+    let mut rest = 0;
+
+    while let Some(i) = text[rest..].find("{%") {
+        let start = rest + i;
+        let end = start + 2 + text[start + 2..].find("%}")? + 2;
+
+        // `{% endif{% endraw %}`, only the last opening starts a tag.
+        if text[start + 2..end - 2].contains("{%") {
+            rest = start + 2;
+            continue;
+        }
+
+        let mut inner = &text[start + 2..end - 2];
+        let trim_before = inner.starts_with('-');
+        if trim_before {
+            inner = &inner[1..];
+        }
+        let trim_after = inner.ends_with('-');
+        if trim_after {
+            inner = &inner[..inner.len() - 1];
+        }
+
+        let tag = inner.split_whitespace().next().unwrap_or_default();
+
+        if tag == name && name == "comment" {
+            depth += 1;
+        } else if tag.strip_prefix("end") == Some(name) {
+            if depth == 0 {
+                return Some(VerbatimEnd {
+                    body_end: start,
+                    tag_end: end,
+                    trim_before,
+                    trim_after,
+                });
+            }
+            depth -= 1;
+        }
+
+        rest = end;
+    }
+
+    None
+}
+
+struct Position {
+    line: usize,
+    column: usize,
+}
+
+impl Position {
+    fn new(state: &LiquidState) -> Self {
+        Self {
+            line: state.current_line + 1,
+            column: state.current_column + 1,
+        }
+    }
+
+    fn location(&self, source: &str) -> Location {
+        Location {
+            line: self.line,
+            column: self.column,
+            source: source.to_string(),
+        }
+    }
+
+    fn advance(&mut self, text: &str) {
+        for c in text.chars() {
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+
+    use super::*;
+    use crate::{
+        config::Config,
+        plugins::Plugins,
+        report::BuildReport,
+        scope::Scopes,
+        template::{BlockOverrides, Templates},
+    };
+
+    fn with_state<T>(f: impl FnOnce(&LiquidState) -> T) -> T {
+        let config: Config = serde_yaml::from_str("output: out\ntheme: default").unwrap();
+        let state = LiquidState {
+            plugins: &Plugins::new(),
+            file_path: "site/test.html",
+            page_path: "site/test.html",
+            current_line: 0,
+            current_column: 0,
+            interrupt: &Cell::new(None),
+            templates: &Templates::default(),
+            config: &config,
+            scopes: &Scopes::default(),
+            blocks: &BlockOverrides::default(),
+            report: &BuildReport::deferred(),
+            trace: &RefCell::default(),
+        };
+
+        f(&state)
+    }
+
+    fn tokens(source: &str) -> Result<Vec<(TokenKind, String)>, String> {
+        with_state(|state| match tokenize(state, source, 0) {
+            Ok(tokens) => Ok(tokens
+                .into_iter()
+                .map(|token| (token.kind, token.markup.to_string()))
+                .collect()),
+            Err(e) => Err(e.message),
+        })
+    }
+
+    fn text(markup: &str) -> (TokenKind, String) {
+        (TokenKind::Text, markup.to_string())
+    }
+
+    fn output(markup: &str) -> (TokenKind, String) {
+        (TokenKind::Output, markup.to_string())
+    }
+
+    fn tag(markup: &str) -> (TokenKind, String) {
+        (TokenKind::Tag, markup.to_string())
+    }
+
+    #[test]
+    fn splits_text_outputs_and_tags() {
+        assert_eq!(
+            tokens("a {{ title }} b {% if x %}c{% endif %}").unwrap(),
+            [
+                text("a "),
+                output("title"),
+                text(" b "),
+                tag("if x"),
+                text("c"),
+                tag("endif"),
+            ]
+        );
+    }
+
+    #[test]
+    fn whitespace_control_trims_neighbouring_text() {
+        assert_eq!(
+            tokens("a \n {{- x -}} \n b").unwrap(),
+            [text("a"), output("x"), text("b")]
+        );
+        assert_eq!(
+            tokens("<ul>\n  {%- for i in x %}\n  <li>").unwrap(),
+            [text("<ul>"), tag("for i in x"), text("\n  <li>")]
+        );
+        assert_eq!(tokens("  {%- if x -%}  ").unwrap(), [tag("if x")]);
+    }
+
+    #[test]
+    fn tags_may_contain_outputs_and_quoted_delimiters() {
+        assert_eq!(
+            tokens("{% include {{ widget }} %}!").unwrap(),
+            [tag("include {{ widget }}"), text("!")]
+        );
+        assert_eq!(
+            tokens(r#"{% assign x = "%}" %}{{ "}}" }}"#).unwrap(),
+            [tag(r#"assign x = "%}""#), output(r#""}}""#)]
+        );
+    }
+
+    #[test]
+    fn raw_bodies_are_verbatim() {
+        assert_eq!(
+            tokens("{% raw %}{{ x }}{% if %}{% endraw %}!").unwrap(),
+            [text("{{ x }}{% if %}"), text("!")]
+        );
+        assert_eq!(
+            tokens("a {%- raw -%} {{ x }} {%- endraw -%} b").unwrap(),
+            [text("a"), text("{{ x }}"), text("b")]
+        );
+    }
+
+    #[test]
+    fn comments_produce_no_tokens() {
+        assert_eq!(
+            tokens("a{% comment %}{% if %}{% comment %}{% endcomment %}{{ x{% endcomment %}b")
+                .unwrap(),
+            [text("a"), text("b")]
+        );
+        assert_eq!(
+            tokens("A{% # don't touch %}B{%- # it's -%} C").unwrap(),
+            [text("A"), text("B"), text("C")]
+        );
+        assert_eq!(
+            tokens("{% liquid\n  # don't\n  echo 'x'\n%}'").unwrap(),
+            [tag("liquid\n  # don't\n  echo 'x'"), text("'")]
+        );
+    }
+
+    #[test]
+    fn locations_count_lines_and_columns() {
+        let location = with_state(|state| {
+            let tokens = tokenize(state, "a\nbc {{ x }}", 0).unwrap();
+            tokens[1].location.clone()
+        });
+
+        assert_eq!((location.line, location.column), (2, 4));
+        assert_eq!(location.source, "{{ x }}");
+    }
+
+    #[test]
+    fn unterminated_tags_are_errors() {
+        assert!(tokens("{{ x").unwrap_err().contains("`}}`"));
+        assert!(tokens("{% if x }}").unwrap_err().contains("`%}`"));
+        assert!(tokens("{% raw %}x").unwrap_err().contains("endraw"));
+    }
+}
@@ -0,0 +1,20 @@
+pub mod ast;
+pub mod config;
+pub mod date;
+pub mod diagnostic;
+mod error;
+pub mod expression;
+pub mod filters;
+pub mod helper;
+pub mod lexer;
+pub mod liquid;
+pub mod markdown;
+pub mod page;
+pub mod parser;
+pub mod plugins;
+pub mod report;
+pub mod scope;
+pub mod site;
+pub mod template;
+
+pub use error::Error;
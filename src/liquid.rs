@@ -1,87 +1,86 @@
 use std::{
-    cell::UnsafeCell,
+    cmp::Ordering,
     collections::HashMap,
     fmt::{self, Debug},
     io::{Error, ErrorKind},
-    ops::{Deref, DerefMut},
-    rc::{Rc, Weak},
+    sync::{Arc, Weak},
 };
 
+use time::OffsetDateTime;
+
 use crate::{
-    helper::{MutRef, Ru, Wu},
+    date,
     parser::{LiquidState, ParseError},
 };
 
 pub type Object = HashMap<String, Liquid>;
 pub type Array = Vec<Liquid>;
 
+/// Objects and arrays are shared and never mutated while shared, changing one
+/// through [`Liquid::with_object`] or [`Liquid::with_array`] copies it first.
 #[derive(Clone)]
 pub enum LiquidInner {
     String(String),
+    /// Trusted HTML, e.g. rendered `content`, that auto-escaping leaves as is.
+    Safe(String),
     Int(i64),
+    Float(f64),
     Bool(bool),
-    Object(Ru<Object>),
-    WeakObject(Wu<Object>),
-    Array(Ru<Array>),
-    WeakArray(Wu<Array>),
+    Date(OffsetDateTime),
+    Object(Arc<Object>),
+    /// A link back up the tree, e.g. the `parent` folder of a page.
+    WeakObject(Weak<Object>),
+    Array(Arc<Array>),
+    WeakArray(Weak<Array>),
     Nil,
 }
 
 impl Debug for LiquidInner {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            LiquidInner::String(arg0) => arg0.fmt(f),
+            LiquidInner::String(arg0) | LiquidInner::Safe(arg0) => arg0.fmt(f),
             LiquidInner::Int(arg0) => arg0.fmt(f),
+            LiquidInner::Float(arg0) => arg0.fmt(f),
             LiquidInner::Bool(arg0) => arg0.fmt(f),
+            LiquidInner::Date(arg0) => date::to_output(arg0).fmt(f),
             LiquidInner::Object(arg0) => {
                 if arg0.contains_key("contents") {
-                    let mut arg0 = arg0.clone();
-                    let value = arg0.insert("contents".to_string(), "{{ contents }}".into());
-
-                    let res = arg0.fmt(f);
+                    let mut arg0 = (**arg0).clone();
+                    arg0.insert("contents".to_string(), "{{ contents }}".into());
 
-                    if let Some(value) = value {
-                        arg0.insert("contents".to_string(), value);
-                    } else {
-                        arg0.remove("contents");
-                    }
-
-                    res
+                    arg0.fmt(f)
                 } else {
                     arg0.fmt(f)
                 }
             }
-            LiquidInner::WeakObject(arg0) => arg0.ru().fmt(f),
+            LiquidInner::WeakObject(arg0) => arg0.upgrade().fmt(f),
             LiquidInner::Array(arg0) => arg0.fmt(f),
-            LiquidInner::WeakArray(arg0) => arg0.ru().fmt(f),
+            LiquidInner::WeakArray(arg0) => arg0.upgrade().fmt(f),
             LiquidInner::Nil => write!(f, "Nil"),
         }
     }
 }
 
-impl Into<Liquid> for LiquidInner {
-    fn into(self) -> Liquid {
-        Liquid {
-            inner: UnsafeCell::new(self),
-        }
+impl From<LiquidInner> for Liquid {
+    fn from(val: LiquidInner) -> Self {
+        Liquid { inner: val }
     }
 }
 
+#[derive(Clone)]
 pub struct Liquid {
-    pub inner: UnsafeCell<LiquidInner>,
+    pub inner: LiquidInner,
 }
 
 impl Debug for Liquid {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.inner.mut_ref().fmt(f)
+        self.inner.fmt(f)
     }
 }
 
-impl Clone for Liquid {
-    fn clone(&self) -> Self {
-        Self {
-            inner: UnsafeCell::new(self.inner.mut_ref().clone()),
-        }
+impl Default for Liquid {
+    fn default() -> Self {
+        LiquidInner::Nil.into()
     }
 }
 
@@ -94,10 +93,19 @@ impl Liquid {
         LiquidInner::String(Default::default()).into()
     }
 
+    /// Marks `html` as trusted so auto-escaping outputs it unchanged.
+    pub fn safe(html: impl Into<String>) -> Self {
+        LiquidInner::Safe(html.into()).into()
+    }
+
     pub fn default_int() -> Self {
         LiquidInner::Int(Default::default()).into()
     }
 
+    pub fn default_float() -> Self {
+        LiquidInner::Float(Default::default()).into()
+    }
+
     pub fn default_bool() -> Self {
         LiquidInner::Bool(Default::default()).into()
     }
@@ -111,265 +119,297 @@ impl Liquid {
     }
 
     pub fn is(&self) -> bool {
-        match &self.inner.mut_ref() {
-            LiquidInner::String(s) => !s.is_empty(),
+        match &self.inner {
+            LiquidInner::String(s) | LiquidInner::Safe(s) => !s.is_empty(),
             LiquidInner::Int(_) => true,
+            LiquidInner::Float(_) => true,
             LiquidInner::Bool(b) => *b,
+            LiquidInner::Date(_) => true,
             LiquidInner::Object(o) => !o.is_empty(),
-            LiquidInner::WeakObject(o) => {
-                if let Some(o) = o.0.upgrade() {
-                    !o.mut_ref().is_empty()
-                } else {
-                    false
-                }
-            }
+            LiquidInner::WeakObject(o) => o.upgrade().is_some_and(|o| !o.is_empty()),
             LiquidInner::Array(o) => !o.is_empty(),
-            LiquidInner::WeakArray(o) => {
-                if let Some(o) = o.0.upgrade() {
-                    !o.mut_ref().is_empty()
-                } else {
-                    false
-                }
-            }
+            LiquidInner::WeakArray(o) => o.upgrade().is_some_and(|o| !o.is_empty()),
             LiquidInner::Nil => false,
         }
     }
 
-    pub fn is_string(&self) -> bool {
-        match self.inner.mut_ref() {
-            LiquidInner::String(_) => true,
-            _ => false,
+    /// Liquid truthiness, only `nil` and `false` are falsy.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(&self.inner, LiquidInner::Nil | LiquidInner::Bool(false))
+    }
+
+    /// Matches the `blank` literal: nil, false, empty or whitespace only.
+    pub fn is_blank(&self) -> bool {
+        match &self.inner {
+            LiquidInner::String(s) | LiquidInner::Safe(s) => s.trim().is_empty(),
+            _ => !self.is_truthy() || self.is_empty(),
         }
     }
 
+    pub fn is_string(&self) -> bool {
+        matches!(&self.inner, LiquidInner::String(_) | LiquidInner::Safe(_))
+    }
+
+    /// Trusted HTML, see [`Liquid::safe`].
+    pub fn is_safe(&self) -> bool {
+        matches!(&self.inner, LiquidInner::Safe(_))
+    }
+
     pub fn is_int(&self) -> bool {
-        match self.inner.mut_ref() {
-            LiquidInner::Int(_) => true,
-            _ => false,
-        }
+        matches!(&self.inner, LiquidInner::Int(_))
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(&self.inner, LiquidInner::Float(_))
     }
 
     pub fn is_bool(&self) -> bool {
-        match self.inner.mut_ref() {
-            LiquidInner::Bool(_) => true,
-            _ => false,
-        }
+        matches!(&self.inner, LiquidInner::Bool(_))
     }
 
     pub fn is_object(&self) -> bool {
-        match self.inner.mut_ref() {
-            LiquidInner::Object(_) => true,
-            LiquidInner::WeakObject(_) => true,
-            _ => false,
-        }
+        matches!(
+            &self.inner,
+            LiquidInner::Object(_) | LiquidInner::WeakObject(_)
+        )
     }
 
     pub fn is_array(&self) -> bool {
-        match self.inner.mut_ref() {
-            LiquidInner::Array(_) => true,
-            LiquidInner::WeakArray(_) => true,
-            _ => false,
-        }
+        matches!(
+            &self.inner,
+            LiquidInner::Array(_) | LiquidInner::WeakArray(_)
+        )
     }
 
     pub fn is_nil(&self) -> bool {
-        match self.inner.mut_ref() {
-            LiquidInner::Nil => true,
-            _ => false,
-        }
+        matches!(&self.inner, LiquidInner::Nil)
     }
 
-    pub fn as_string(&self) -> Option<&mut String> {
-        match self.inner.mut_ref() {
-            LiquidInner::String(s) => Some(s),
+    pub fn as_string(&self) -> Option<&String> {
+        match &self.inner {
+            LiquidInner::String(s) | LiquidInner::Safe(s) => Some(s),
             _ => None,
         }
     }
 
     pub fn as_int(&self) -> Option<i64> {
-        match self.inner.mut_ref() {
+        match &self.inner {
             LiquidInner::Int(i) => Some(*i),
             _ => None,
         }
     }
 
+    pub fn as_float(&self) -> Option<f64> {
+        match &self.inner {
+            LiquidInner::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub fn as_date(&self) -> Option<OffsetDateTime> {
+        match &self.inner {
+            LiquidInner::Date(d) => Some(*d),
+            _ => None,
+        }
+    }
+
     pub fn as_bool(&self) -> Option<bool> {
-        match self.inner.mut_ref() {
+        match &self.inner {
             LiquidInner::Bool(b) => Some(*b),
             _ => None,
         }
     }
 
-    pub fn as_object(&self) -> Option<Ru<Object>> {
-        match self.inner.mut_ref() {
+    pub fn as_object(&self) -> Option<Arc<Object>> {
+        match &self.inner {
             LiquidInner::Object(o) => Some(o.clone()),
-            LiquidInner::WeakObject(o) => {
-                if let Some(o) = o.0.upgrade() {
-                    Some(Ru(o))
-                } else {
-                    None
-                }
-            }
+            LiquidInner::WeakObject(o) => o.upgrade(),
             _ => None,
         }
     }
 
-    pub fn as_array(&self) -> Option<Ru<Array>> {
-        match self.inner.mut_ref() {
+    pub fn as_array(&self) -> Option<Arc<Array>> {
+        match &self.inner {
             LiquidInner::Array(o) => Some(o.clone()),
-            LiquidInner::WeakArray(o) => {
-                if let Some(o) = o.0.upgrade() {
-                    Some(Ru(o))
-                } else {
-                    None
-                }
-            }
+            LiquidInner::WeakArray(o) => o.upgrade(),
             _ => None,
         }
     }
 
     pub fn len(&self) -> Option<usize> {
-        match self.inner.mut_ref() {
+        match &self.inner {
             LiquidInner::Bool(_) => None,
             LiquidInner::Int(_) => None,
+            LiquidInner::Float(_) => None,
+            LiquidInner::Date(_) => None,
             LiquidInner::Nil => None,
-            LiquidInner::String(s) => Some(s.len()),
-            LiquidInner::Object(_) | LiquidInner::WeakObject(_) => {
-                Some(self.as_object()?.len())
-            }
-            LiquidInner::Array(_) | LiquidInner::WeakArray(_) => {
-                Some(self.as_array()?.len())
-            }
+            LiquidInner::String(s) | LiquidInner::Safe(s) => Some(s.len()),
+            LiquidInner::Object(_) | LiquidInner::WeakObject(_) => Some(self.as_object()?.len()),
+            LiquidInner::Array(_) | LiquidInner::WeakArray(_) => Some(self.as_array()?.len()),
         }
     }
 
-    pub fn with_string<T>(&self, mut f: impl FnMut(&mut String)) {
-        if let Some(v) = self.as_string() {
-            f(v)
+    /// Matches the `empty` literal: an empty string, array or object.
+    pub fn is_empty(&self) -> bool {
+        self.len() == Some(0)
+    }
+
+    /// Text written to the page when the value is used in an output.
+    pub fn to_output(&self) -> String {
+        match &self.inner {
+            LiquidInner::String(s) | LiquidInner::Safe(s) => s.clone(),
+            LiquidInner::Int(i) => i.to_string(),
+            LiquidInner::Float(f) if f.fract() == 0.0 && f.is_finite() => format!("{f:.1}"),
+            LiquidInner::Float(f) => f.to_string(),
+            LiquidInner::Bool(b) => b.to_string(),
+            LiquidInner::Date(d) => date::to_output(d),
+            LiquidInner::Nil => String::new(),
+            LiquidInner::Array(_) | LiquidInner::WeakArray(_) => match self.as_array() {
+                Some(a) => a.iter().map(|l| l.to_output()).collect(),
+                None => String::new(),
+            },
+            LiquidInner::Object(_) | LiquidInner::WeakObject(_) => format!("{self:?}"),
         }
     }
 
-    pub fn with_int<T>(&self, mut f: impl FnMut(i64)) {
-        if let Some(v) = self.as_int() {
-            f(v)
+    pub fn with_string<T>(&mut self, mut f: impl FnMut(&mut String) -> T) -> Option<T> {
+        match &mut self.inner {
+            LiquidInner::String(s) | LiquidInner::Safe(s) => Some(f(s)),
+            _ => None,
+        }
+    }
+
+    pub fn with_int<T>(&self, mut f: impl FnMut(i64) -> T) -> Option<T> {
+        Some(f(self.as_int()?))
+    }
+
+    pub fn with_bool<T>(&self, mut f: impl FnMut(bool) -> T) -> Option<T> {
+        Some(f(self.as_bool()?))
+    }
+
+    /// Changes the object, copying it first when it is shared. Weak links are
+    /// read only and give `None`.
+    pub fn with_object<T>(&mut self, mut f: impl FnMut(&mut Object) -> T) -> Option<T> {
+        match &mut self.inner {
+            LiquidInner::Object(o) => Some(f(Arc::make_mut(o))),
+            _ => None,
         }
     }
 
-    pub fn with_bool<T>(&self, mut f: impl FnMut(bool)) {
-        if let Some(v) = self.as_bool() {
-            f(v)
+    /// Changes the array, copying it first when it is shared. Weak links are
+    /// read only and give `None`.
+    pub fn with_array<T>(&mut self, mut f: impl FnMut(&mut Array) -> T) -> Option<T> {
+        match &mut self.inner {
+            LiquidInner::Array(a) => Some(f(Arc::make_mut(a))),
+            _ => None,
         }
     }
+}
+
+impl PartialEq for Liquid {
+    fn eq(&self, other: &Self) -> bool {
+        if let (Some(a), Some(b)) = (self.as_string(), other.as_string()) {
+            return a == b;
+        }
 
-    pub fn with_object<T>(&self, mut f: impl FnMut(&mut Object)) {
-        if let Some(mut v) = self.as_object() {
-            f(&mut v)
+        match (&self.inner, &other.inner) {
+            (LiquidInner::Int(a), LiquidInner::Int(b)) => a == b,
+            (LiquidInner::Float(a), LiquidInner::Float(b)) => a == b,
+            (LiquidInner::Int(a), LiquidInner::Float(b)) => *a as f64 == *b,
+            (LiquidInner::Float(a), LiquidInner::Int(b)) => *a == *b as f64,
+            (LiquidInner::Bool(a), LiquidInner::Bool(b)) => a == b,
+            (LiquidInner::Date(a), LiquidInner::Date(b)) => a == b,
+            (LiquidInner::Nil, LiquidInner::Nil) => true,
+            (LiquidInner::Array(a), LiquidInner::Array(b)) => **a == **b,
+            (LiquidInner::Object(a), LiquidInner::Object(b)) => **a == **b,
+            // Weak links point back up the tree, compare them by identity to avoid cycles.
+            (LiquidInner::WeakArray(a), LiquidInner::WeakArray(b)) => a.ptr_eq(b),
+            (LiquidInner::WeakObject(a), LiquidInner::WeakObject(b)) => a.ptr_eq(b),
+            _ => false,
         }
     }
+}
 
-    pub fn with_array<T>(&self, mut f: impl FnMut(&mut Array)) {
-        if let Some(mut v) = self.as_array() {
-            f(&mut v)
+impl PartialOrd for Liquid {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if let (Some(a), Some(b)) = (self.as_string(), other.as_string()) {
+            return a.as_str().partial_cmp(b.as_str());
+        }
+
+        match (&self.inner, &other.inner) {
+            (LiquidInner::Int(a), LiquidInner::Int(b)) => (*a).partial_cmp(b),
+            (LiquidInner::Float(a), LiquidInner::Float(b)) => (*a).partial_cmp(b),
+            (LiquidInner::Int(a), LiquidInner::Float(b)) => (*a as f64).partial_cmp(b),
+            (LiquidInner::Float(a), LiquidInner::Int(b)) => (*a).partial_cmp(&(*b as f64)),
+            (LiquidInner::Date(a), LiquidInner::Date(b)) => (*a).partial_cmp(b),
+            _ => None,
         }
     }
 }
 
 impl From<()> for Liquid {
     fn from(_: ()) -> Self {
-        Self {
-            inner: UnsafeCell::new(LiquidInner::Nil),
-        }
+        LiquidInner::Nil.into()
     }
 }
 
 impl From<bool> for Liquid {
     fn from(value: bool) -> Self {
-        Self {
-            inner: UnsafeCell::new(LiquidInner::Bool(value)),
-        }
+        LiquidInner::Bool(value).into()
     }
 }
 
 impl From<i64> for Liquid {
     fn from(value: i64) -> Self {
-        Self {
-            inner: UnsafeCell::new(LiquidInner::Int(value)),
-        }
+        LiquidInner::Int(value).into()
     }
 }
 
-impl From<String> for Liquid {
-    fn from(value: String) -> Self {
-        Self {
-            inner: UnsafeCell::new(LiquidInner::String(value)),
-        }
+impl From<f64> for Liquid {
+    fn from(value: f64) -> Self {
+        LiquidInner::Float(value).into()
     }
 }
 
-impl From<&str> for Liquid {
-    fn from(value: &str) -> Self {
-        Self {
-            inner: UnsafeCell::new(LiquidInner::String(value.to_string())),
-        }
+impl From<OffsetDateTime> for Liquid {
+    fn from(value: OffsetDateTime) -> Self {
+        LiquidInner::Date(value).into()
     }
 }
 
-impl From<Ru<Object>> for Liquid {
-    fn from(value: Ru<Object>) -> Self {
-        if value.1 {
-            Self {
-                inner: UnsafeCell::new(LiquidInner::WeakObject(Rc::downgrade(&value.0))),
-            }
-        } else {
-            Self {
-                inner: UnsafeCell::new(LiquidInner::Object(value)),
-            }
-        }
+impl From<String> for Liquid {
+    fn from(value: String) -> Self {
+        LiquidInner::String(value).into()
     }
 }
 
-impl From<Ru<Array>> for Liquid {
-    fn from(value: Ru<Array>) -> Self {
-        if value.1 {
-            Self {
-                inner: UnsafeCell::new(LiquidInner::WeakArray(Rc::downgrade(&value.0))),
-            }
-        } else {
-            Self {
-                inner: UnsafeCell::new(LiquidInner::Array(value)),
-            }
-        }
+impl From<&str> for Liquid {
+    fn from(value: &str) -> Self {
+        LiquidInner::String(value.to_string()).into()
     }
 }
 
-#[derive(Debug, Default, Clone)]
-pub struct MutRc<T>(
-    pub Rc<UnsafeCell<T>>,
-    /// Is weak reference
-    pub bool,
-);
-
-impl<T> MutRc<T> {
-    pub fn new(x: T) -> Self {
-        Self(Rc::new(UnsafeCell::new(x)), false)
+impl From<Arc<Object>> for Liquid {
+    fn from(value: Arc<Object>) -> Self {
+        LiquidInner::Object(value).into()
     }
+}
 
-    pub fn get_mut(&self) -> &mut T {
-        self.0.mut_ref()
+impl From<Weak<Object>> for Liquid {
+    fn from(value: Weak<Object>) -> Self {
+        LiquidInner::WeakObject(value).into()
     }
 }
 
-impl<T> Deref for MutRc<T> {
-    type Target = T;
-
-    fn deref(&self) -> &Self::Target {
-        self.get_mut()
+impl From<Arc<Array>> for Liquid {
+    fn from(value: Arc<Array>) -> Self {
+        LiquidInner::Array(value).into()
     }
 }
 
-impl<T> DerefMut for MutRc<T> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        self.get_mut()
+impl From<Weak<Array>> for Liquid {
+    fn from(value: Weak<Array>) -> Self {
+        LiquidInner::WeakArray(value).into()
     }
 }
 
@@ -381,7 +421,7 @@ impl<'local, T> OptionToResult<'local, T, ParseError, LiquidState<'local>> for O
     fn result(self, _: &LiquidState) -> Result<T, ParseError> {
         match self {
             Some(o) => Ok(o),
-            None => Err(ParseError::new(format!("Option::None"))),
+            None => Err(ParseError::new("Option::None".to_string())),
         }
     }
 }
@@ -394,3 +434,44 @@ impl<'local, T> OptionToResult<'local, T, Error, ()> for Option<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_do_not_leak_into_copies() {
+        let mut object = Object::new();
+        object.insert("title".to_string(), "old".into());
+        let original: Liquid = Arc::new(object).into();
+
+        let mut copy = original.clone();
+        copy.with_object(|o| o.insert("title".to_string(), "new".into()));
+
+        assert_eq!(original.as_object().unwrap()["title"], "old".into());
+        assert_eq!(copy.as_object().unwrap()["title"], "new".into());
+
+        let original: Liquid = Arc::new(vec![1.into(), 2.into()]).into();
+        let mut copy = original.clone();
+        copy.with_array(|a| a.reverse());
+
+        assert_eq!(original.as_array().unwrap()[0], 1.into());
+        assert_eq!(copy.as_array().unwrap()[0], 2.into());
+    }
+
+    #[test]
+    fn weak_links_are_read_only() {
+        let parent = Arc::new(Object::from([("title".to_string(), "docs".into())]));
+        let mut link: Liquid = Arc::downgrade(&parent).into();
+
+        assert_eq!(link.with_object(|o| o.clear()), None);
+        assert_eq!(link.as_object().unwrap()["title"], "docs".into());
+        assert_eq!(parent.len(), 1);
+    }
+
+    #[test]
+    fn values_are_thread_safe() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Liquid>();
+    }
+}
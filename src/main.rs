@@ -2,25 +2,53 @@ fn main() {
     let args = Args::parse();
 
     if let Some(cd) = args.cd {
-        env::set_current_dir(&cd).exit(cd);
+        if let Err(e) = env::set_current_dir(&cd) {
+            diagnostic::print_error(&Error::io(cd, e));
+            process::exit(1);
+        }
     }
 
     match args.command {
         Cmd::Serve => site::serve(),
-        Cmd::Build => site::build(),
+        Cmd::Build { fail_on_warnings } => build(fail_on_warnings),
         Cmd::New { name } => init::init_in(&name),
         Cmd::Init => init::init(),
         Cmd::Post { name: _, path: _ } => todo!(),
     }
 }
 
+/// Builds the site and exits with 1 on errors, or on warnings too with
+/// `fail_on_warnings`.
+fn build(fail_on_warnings: bool) {
+    let report = BuildReport::default();
+
+    if let Err(e) = site::build(&report) {
+        report.error(e);
+    }
+
+    report.print_summary();
+
+    if report.failed(fail_on_warnings) {
+        process::exit(1);
+    }
+}
+
 #[derive(clap::Subcommand)]
 enum Cmd {
     Serve,
-    Build,
-    New { name: String },
+    Build {
+        /// Exit with a failure when the build has warnings too.
+        #[arg(long)]
+        fail_on_warnings: bool,
+    },
+    New {
+        name: String,
+    },
     Init,
-    Post { name: String, path: Option<String> },
+    Post {
+        name: String,
+        path: Option<String>,
+    },
 }
 
 #[derive(Parser)]
@@ -33,17 +61,9 @@ struct Args {
     command: Cmd,
 }
 
-use std::env;
+use std::{env, process};
 
 use clap::Parser;
-use helper::IoError;
+use fluid::{diagnostic, report::BuildReport, site, Error};
 
-pub mod config;
-pub mod helper;
 mod init;
-pub mod liquid;
-pub mod markdown;
-pub mod page;
-pub mod parser;
-pub mod plugins;
-pub mod site;
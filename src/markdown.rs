@@ -1,8 +1,7 @@
-use std::rc::{Rc, Weak};
+use std::sync::OnceLock;
 
 use comrak::{
-    self, adapters::SyntaxHighlighterAdapter, plugins::syntect::SyntectAdapter, ComrakOptions,
-    ComrakPlugins, ComrakRenderOptions,
+    self, plugins::syntect::SyntectAdapter, ComrakOptions, ComrakPlugins, ComrakRenderOptions,
 };
 
 const MARKDOWN_OPTIONS: ComrakOptions = {
@@ -37,23 +36,10 @@ const MARKDOWN_OPTIONS: ComrakOptions = {
     }
 };
 
-static mut MARKDOWN_ADAPTER: Option<SyntectAdapter> = None;
+static MARKDOWN_ADAPTER: OnceLock<SyntectAdapter> = OnceLock::new();
 
 fn get_markdown_adapter() -> &'static SyntectAdapter {
-    unsafe {
-        match &MARKDOWN_ADAPTER {
-            Some(adapter) => adapter,
-            None => {
-                let adapter = SyntectAdapter::new("base16-ocean.light");
-                MARKDOWN_ADAPTER = Some(adapter);
-
-                match &MARKDOWN_ADAPTER {
-                    Some(adapter) => adapter,
-                    None => panic!("Expected to have an adapter."),
-                }
-            }
-        }
-    }
+    MARKDOWN_ADAPTER.get_or_init(|| SyntectAdapter::new("base16-ocean.light"))
 }
 
 pub fn markdown(text: &str) -> String {
@@ -69,33 +55,3 @@ pub fn markdown(text: &str) -> String {
 
     comrak::markdown_to_html_with_plugins(text, options, &plugins)
 }
-
-struct CssAdapter {}
-
-impl SyntaxHighlighterAdapter for CssAdapter {
-    fn write_highlighted(
-        &self,
-        output: &mut dyn std::io::Write,
-        lang: Option<&str>,
-        code: &str,
-    ) -> std::io::Result<()> {
-        todo!()
-    }
-
-    fn write_pre_tag(
-        &self,
-        output: &mut dyn std::io::Write,
-        attributes: std::collections::HashMap<String, String>,
-    ) -> std::io::Result<()> {
-        todo!()
-    }
-
-    fn write_code_tag(
-        &self,
-        output: &mut dyn std::io::Write,
-        attributes: std::collections::HashMap<String, String>,
-    ) -> std::io::Result<()> {
-
-        todo!()
-    }
-}
@@ -1,19 +1,35 @@
 use std::{
-    cell::UnsafeCell,
+    cell::{Cell, RefCell},
     collections::HashMap,
     fmt::{self, Display},
     fs::{self, remove_dir_all, remove_file, DirEntry},
-    io::{self, Error},
+    io,
     path::{Path, PathBuf},
-    rc::Rc,
+    sync::{
+        atomic::{self, AtomicUsize},
+        Arc, Weak,
+    },
+    thread,
 };
 
 use yaml_rust::Yaml;
 
 use crate::{
+    ast::Node,
+    config::Config,
+    date::{self, Zone},
+    diagnostic,
+    expression::parse_expression,
+    filters,
     helper::IntoIoResult,
-    liquid::{Liquid, LiquidInner, MutRc, Object, OptionToResult},
+    helper::WithPath,
+    lexer::Location,
+    liquid::{Array, Liquid, Object, OptionToResult},
     plugins::Plugins,
+    report::{BuildReport, Stage},
+    scope::Scopes,
+    template::{BlockOverrides, Template, Templates},
+    Error,
 };
 
 pub type LiquidResult = Result<Liquid, ParseError>;
@@ -21,8 +37,29 @@ pub type LiquidResult = Result<Liquid, ParseError>;
 pub struct LiquidState<'a> {
     pub plugins: &'a Plugins,
     pub file_path: &'a str,
+    /// The page being rendered, problems found in its layouts and includes
+    /// are reported for it.
+    pub page_path: &'a str,
     pub current_line: usize,
     pub current_column: usize,
+    /// Set by `break`/`continue`, consumed by the enclosing loop.
+    pub interrupt: &'a Cell<Option<Interrupt>>,
+    pub templates: &'a Templates,
+    pub config: &'a Config,
+    /// Variables of the render, `object` is only read.
+    pub scopes: &'a Scopes,
+    /// `block` overrides of the templates extending the one being rendered.
+    pub blocks: &'a BlockOverrides<'a>,
+    pub report: &'a BuildReport,
+    /// The nodes being rendered, innermost last, to locate problems that are
+    /// reported without stopping the render.
+    pub trace: &'a RefCell<Vec<StackInfo>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    Break,
+    Continue,
 }
 
 impl<'a> LiquidState<'a> {
@@ -32,254 +69,195 @@ impl<'a> LiquidState<'a> {
         let mut temp = liquid.clone();
 
         for key in keys {
-            if let Some(f) = self.plugins.functions.get(key) {
-                temp = f(self, &temp)?;
-
-                continue;
-            }
-
-            if temp.is_object() {
-                if let Some(value) = temp.as_object().unwrap().get(key) {
-                    temp = value.clone();
-                } else {
-                    return Err(format!("Optional::None").into());
-                }
-
-                continue;
-            }
+            temp = self.get_step(&temp, key)?;
+        }
 
-            if temp.is_array() {
-                let index = match key.parse::<usize>() {
-                    Ok(v) => v,
-                    Err(e) => return Err(format!("{e}").into()),
-                };
-                if let Some(value) = temp.as_array().unwrap().get(index) {
-                    temp = value.clone();
-                } else {
-                    return Err(format!("Optional::None").into());
-                }
+        Ok(temp)
+    }
 
-                continue;
-            }
+    /// Resolves the first name of a path: scope variables shadow `page`,
+    /// which is the page object itself, and the page's own fields.
+    pub fn find_variable(&self, page: &Liquid, name: &str) -> Result<Option<Liquid>, ParseError> {
+        if let Some(value) = self.scopes.get(name) {
+            return Ok(Some(value));
+        }
 
-            return Err(format!("Cannot Index Into").into());
+        if name == PAGE && page.as_object().is_some_and(|o| !o.contains_key(PAGE)) {
+            return Ok(Some(page.clone()));
         }
 
-        Ok(temp)
+        self.find_step(page, name)
     }
-}
 
-pub static mut LAYOUTS: Option<HashMap<String, String>> = None;
+    /// Resolves one `.key` of a path on `temp`.
+    pub fn get_step(&self, temp: &Liquid, key: &str) -> Result<Liquid, ParseError> {
+        match self.find_step(temp, key)? {
+            Some(value) => Ok(value),
+            None => Err(format!("`{key}` is not defined.").into()),
+        }
+    }
 
-const SITE: &str = "site";
-const LAYOUTS_FOLDER: &str = "layouts";
+    /// Resolves one `.key` of a path on `temp`, `None` when it is undefined.
+    pub fn find_step(&self, temp: &Liquid, key: &str) -> Result<Option<Liquid>, ParseError> {
+        if let Some(f) = self.plugins.functions.get(key) {
+            return Ok(Some(f(self, temp)?));
+        }
 
-pub const PAGE_CONTENT: &str = "contents";
-const PAGE_PARENT: &str = "parent";
-const PAGE_PATH: &str = "path";
-const PAGE_OUT_FOLDER: &str = "out";
-const PAGE_LAYOUT: &str = "layout";
-pub const PAGE_FRONT_MATTER_OFFSET: &str = "front_matter_offset";
+        if let Some(object) = temp.as_object() {
+            if let Some(value) = object.get(key) {
+                return Ok(Some(value.clone()));
+            }
 
-const FOLDER_FIELDS_FOLDER: &str = "folders";
-const FOLDER_FIELDS_FILES: &str = "files";
+            if key == "size" {
+                return Ok(Some((object.len() as i64).into()));
+            }
 
-pub fn site_parse(liquid_tags: &Plugins) -> Result<(), Error> {
-    fn create_folder_starter<P: AsRef<Path>>(working_dir: P) -> Result<Liquid, io::Error> {
-        let base = Liquid::default_object();
+            return Ok(None);
+        }
 
-        let working_dir: &Path = working_dir.as_ref();
+        if let Some(array) = temp.as_array() {
+            return Ok(match key {
+                "size" => Some((array.len() as i64).into()),
+                "first" => Some(array.first().cloned().unwrap_or_default()),
+                "last" => Some(array.last().cloned().unwrap_or_default()),
+                key => match key.parse::<i64>() {
+                    Ok(index) => self.find_index(temp, index),
+                    Err(_) => None,
+                },
+            });
+        }
 
-        base.with_object(|object| {
-            object.insert(FOLDER_FIELDS_FOLDER.to_string(), Liquid::default_array());
-            object.insert(FOLDER_FIELDS_FILES.to_string(), Liquid::default_array());
+        if let (Some(s), "size") = (temp.as_string(), key) {
+            return Ok(Some((s.chars().count() as i64).into()));
+        }
 
-            for e in fs::read_dir(working_dir).unwrap() {
-                fn inner(e: Result<DirEntry, io::Error>, parent: Liquid) -> Result<(), io::Error> {
-                    let e = e?;
-                    let path = e.path();
+        Ok(None)
+    }
 
-                    match e.file_type()?.is_dir() {
-                        true => create_folder(path, parent.as_object().io_result()?),
-                        false => create_file(path, parent.as_object().io_result()?),
-                    }
-                }
+    /// Resolves `[index]` on an array, negative indices count from the end.
+    pub fn find_index(&self, temp: &Liquid, index: i64) -> Option<Liquid> {
+        let array = temp.as_array()?;
 
-                match inner(e, base.clone()) {
-                    Ok(_) => (),
-                    Err(e) => {
-                        eprint!("\n\n{}\n\n", e);
-                        continue;
-                    }
-                }
-            }
+        let index = match index < 0 {
+            true => array.len() as i64 + index,
+            false => index,
+        };
 
-            Some(())
-        })
-        .io_result()?;
+        usize::try_from(index)
+            .ok()
+            .and_then(|i| array.get(i))
+            .cloned()
+    }
 
-        Ok(base)
+    /// Reports a problem that does not stop the render.
+    pub fn warn(&self, message: &str) {
+        let stage = Stage::of_path(self.file_path);
+        self.report.warning(stage, self.file_path, message);
     }
 
-    #[deprecated]
-    fn parse_nodes(plugins: &Plugins, node: &mut HashMap<String, Liquid>) {
-        let folders = node
-            .get(FOLDER_FIELDS_FOLDER)
-            .expect("Object does not contain argument.")
-            .as_array()
-            .result(&())
-            .unwrap();
-        let mut files = node
-            .get(FOLDER_FIELDS_FILES)
-            .expect("Object does not contain argument.")
-            .as_array()
-            .result(&())
-            .unwrap();
+    /// Reports `error` at the node being rendered without stopping the
+    /// render, the page fails once it is rendered.
+    pub fn report_error(&self, error: ParseError) {
+        let error = ParseError {
+            stack: self.trace.borrow().iter().rev().cloned().collect(),
+            ..error
+        };
 
-        for folder_liq in folders.iter() {
-            let mut file = folder_liq.as_object().result(&()).unwrap();
+        self.report.error(Error::render(self.page_path, error));
+    }
 
-            parse_nodes(plugins, &mut file)
-        }
+    /// Reports `variable` at the node being rendered, strict mode renders it
+    /// as nil so every undefined variable of the page is reported.
+    pub fn undefined(&self, variable: &str) {
+        self.report_error(ParseError::new(format!("`{variable}` is not defined.")));
+    }
 
-        for mut file_liq in files.iter_mut() {
-            fn parse_file(plugins: &Plugins, object: &mut Liquid) -> Result<(), io::Error> {
-                let file = object.as_object().result(&())?;
-                let file = (&file).get_mut();
+    /// Evaluates a tag argument, a literal, range or variable path.
+    pub fn get_value(&self, liquid: &Liquid, expression: &str) -> Result<Liquid, ParseError> {
+        parse_expression(expression)?.evaluate(self, liquid)
+    }
+}
 
-                let content = file.get(PAGE_CONTENT).unwrap().as_string().result(&())?;
-                let path = file.get(PAGE_PATH).unwrap().as_string().result(&())?;
+/// Splits tag arguments on whitespace, keeping quoted strings whole.
+pub fn split_arguments(tag: &str) -> Vec<&str> {
+    let mut arguments = Vec::new();
 
-                let out_path_rel = &path[SITE.len() + 1..];
-                let mut out_path = PathBuf::from(PAGE_OUT_FOLDER);
-                out_path.push(out_path_rel);
+    let mut start = None;
+    let mut quote = None;
 
-                {
-                    if let Some(out_str) = out_path.to_str() {
-                        let out_str = out_str.replace(".md", ".html");
-                        out_path = PathBuf::from(out_str)
-                    } else {
-                        eprintln!("Why")
-                    }
+    for (i, c) in tag.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => (),
+            None if c.is_whitespace() => {
+                if let Some(s) = start.take() {
+                    arguments.push(&tag[s..i]);
                 }
+            }
+            None => {
+                start.get_or_insert(i);
+                if c == '"' || c == '\'' {
+                    quote = Some(c);
+                }
+            }
+        }
+    }
 
-                let mut offset = 0;
-
-                let layout = match file.get(PAGE_LAYOUT) {
-                    Some(s) => s.as_string().result(&())?,
-                    None => {
-                        offset = file
-                            .get(PAGE_FRONT_MATTER_OFFSET)
-                            .expect("Created by application.")
-                            .as_int()
-                            .unwrap_or_default();
+    if let Some(s) = start {
+        arguments.push(&tag[s..]);
+    }
 
-                        if offset != 0 {
-                            offset += 3;
-                        }
+    arguments
+}
 
-                        "paste_in"
-                    } // TODO:(frac) get rid of str
-                };
+const SITE: &str = "site";
+const PAGE: &str = "page";
+const LAYOUTS_FOLDER: &str = "layouts";
 
-                fn get_layout(file_name: &str) -> Option<&String> {
-                    unsafe {
-                        match &mut LAYOUTS {
-                            Some(l) => l.get(file_name),
-                            None => {
-                                let mut layouts = HashMap::new();
-
-                                let read_dir = match fs::read_dir(LAYOUTS_FOLDER) {
-                                    Ok(read_dir) => read_dir,
-                                    Err(e) => match e.kind() {
-                                        io::ErrorKind::NotFound => {
-                                            fs::create_dir(LAYOUTS_FOLDER)
-                                                .expect("Failed to create layouts");
-                                            LAYOUTS = Some(HashMap::new());
-                                            return None;
-                                        }
-                                        _ => unimplemented!(),
-                                    },
-                                };
-
-                                for e in read_dir {
-                                    let e = e.unwrap_or_else(|e| panic!("{e}"));
-
-                                    let name = e.file_name().to_str().unwrap().to_string();
-                                    let contents = fs::read_to_string(e.path()).unwrap();
-
-                                    layouts.insert(name, contents);
-                                }
-
-                                LAYOUTS = Some(layouts);
-                                match &mut LAYOUTS {
-                                    Some(l) => l.get(file_name),
-                                    None => std::hint::unreachable_unchecked(),
-                                }
-                            }
-                        }
-                    }
-                }
+pub const PAGE_CONTENT: &str = "contents";
+const PAGE_PARENT: &str = "parent";
+const PAGE_PATH: &str = "path";
+const PAGE_OUT_FOLDER: &str = "out";
+const PAGE_LAYOUT: &str = "layout";
+pub const PAGE_FRONT_MATTER_OFFSET: &str = "front_matter_offset";
 
-                fn walk_write(path: PathBuf, content: &str) -> Result<(), io::Error> {
-                    if let Err(e) = fs::write(&path, content) {
-                        match e.kind() {
-                            io::ErrorKind::NotFound => {
-                                let mut folder_path = path.clone();
-                                folder_path.pop();
-                                fs::DirBuilder::new().recursive(true).create(folder_path)?;
-                                return walk_write(path, content);
-                            }
-                            _ => return Err(e),
-                        }
-                    }
-                    Ok(())
-                }
+const FOLDER_FIELDS_FOLDER: &str = "folders";
+const FOLDER_FIELDS_FILES: &str = "files";
 
-                match layout {
-                    "paste_in" => {
-                        let state = LiquidState {
-                            plugins,
-                            file_path: path,
-                            current_line: 0,
-                            current_column: 0,
-                        };
-
-                        match parse(&state, &object, content, offset as _) {
-                            Ok(parsed) => walk_write(out_path, &parsed),
-                            Err(e) => Err(e.into()),
-                        }
-                    }
-                    any => {
-                        let state = LiquidState {
-                            plugins,
-                            file_path: &format!("layouts/{any}"),
-                            current_line: 0,
-                            current_column: 0,
-                        };
-
-                        match get_layout(any) {
-                            Some(layout) => match parse(&state, &object, layout, offset as _) {
-                                Ok(parsed) => walk_write(out_path, &parsed),
-                                Err(e) => Err(e.into()),
-                            },
-                            None => Err(io::Error::new(
-                                io::ErrorKind::InvalidData,
-                                format!("Layout `{any}` could not found."),
-                            )),
-                        }
-                    }
-                }
-            }
+/// Builds the site, problems of single pages, layouts and assets are added to
+/// `report` and do not stop the build.
+pub fn site_parse(
+    liquid_tags: &Plugins,
+    config: &Config,
+    report: &BuildReport,
+) -> Result<(), Error> {
+    /// Every page of `folder` and its sub folders, sub folders first.
+    fn collect_pages(folder: &Object, pages: &mut Vec<Liquid>) {
+        let folders = folder
+            .get(FOLDER_FIELDS_FOLDER)
+            .and_then(|folders| folders.as_array())
+            .unwrap_or_default();
+        let files = folder
+            .get(FOLDER_FIELDS_FILES)
+            .and_then(|files| files.as_array())
+            .unwrap_or_default();
 
-            if let Err(e) = parse_file(plugins, &mut file_liq) {
-                eprintln!("{e}");
-            }
+        for folder in folders.iter().filter_map(|folder| folder.as_object()) {
+            collect_pages(&folder, pages);
         }
+
+        pages.extend(files.iter().cloned());
     }
 
+    /// Nothing to clear when `path` does not exist yet, e.g. on a first build.
     fn clear_dir<P: AsRef<Path>>(path: P) -> Result<(), io::Error> {
-        for e in fs::read_dir(path)? {
+        let entries = match fs::read_dir(path) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        for e in entries {
             let e = e?;
 
             match e.file_type()?.is_dir() {
@@ -291,36 +269,37 @@ pub fn site_parse(liquid_tags: &Plugins) -> Result<(), Error> {
     }
 
     // ! If we get absolute path this function will fail
-    fn generate_assets<P: AsRef<Path>>(assets_dir: P) -> Result<(), io::Error> {
+    fn generate_assets<P: AsRef<Path>>(assets_dir: P, report: &BuildReport) -> Result<(), Error> {
+        let assets_dir = assets_dir.as_ref();
         let mut output_dir = PathBuf::from("out");
-        output_dir.push(&assets_dir);
-        fs::create_dir(output_dir)?;
-
-        for e in fs::read_dir(assets_dir)? {
-            fn inner(e: Result<DirEntry, io::Error>) -> Result<(), io::Error> {
-                let e = e?;
-
-                match e.file_type()?.is_dir() {
-                    true => generate_assets(e.path()),
+        output_dir.push(assets_dir);
+        fs::create_dir_all(&output_dir).with_path(&output_dir)?;
+
+        for e in fs::read_dir(assets_dir).with_path(assets_dir)? {
+            fn inner(
+                e: Result<DirEntry, io::Error>,
+                assets_dir: &Path,
+                report: &BuildReport,
+            ) -> Result<(), Error> {
+                let e = e.with_path(assets_dir)?;
+                let path = e.path();
+
+                match e.file_type().with_path(&path)?.is_dir() {
+                    true => generate_assets(path, report),
                     false => {
-                        let path = e.path();
                         let mut out = PathBuf::from("out");
                         out.push(&path);
 
-                        if let Err(e) = fs::copy(path, out) {
-                            return Err(e);
-                        };
-
-                        Ok(())
+                        match fs::copy(&path, out) {
+                            Ok(_) => Ok(()),
+                            Err(source) => Err(Error::Asset { path, source }),
+                        }
                     }
                 }
             }
 
-            match inner(e) {
-                Ok(_) => (),
-                Err(e) => {
-                    eprintln!("\n\n{e}\n\n")
-                }
+            if let Err(e) = inner(e, assets_dir, report) {
+                report.error(e);
             }
         }
 
@@ -329,23 +308,259 @@ pub fn site_parse(liquid_tags: &Plugins) -> Result<(), Error> {
 
     let working_dir = PathBuf::from("site");
 
-    let parent = create_folder_starter(working_dir).unwrap();
+    let parent = create_folder(&working_dir, None, config.time_zone(), report)?;
 
-    clear_dir("out").unwrap_or_else(|e| eprintln!("Could not clean the \"out\" folder. {e}"));
+    if let Err(e) = clear_dir(PAGE_OUT_FOLDER) {
+        report.error(Error::io(PAGE_OUT_FOLDER, e));
+    }
 
     let assets_path = PathBuf::from("assets");
     if !assets_path.exists() {
-        fs::create_dir(&assets_path)
-            .unwrap_or_else(|e| eprintln!("Could not create \"assets\" folder. {e}"));
+        if let Err(e) = fs::create_dir(&assets_path) {
+            report.error(Error::io(&assets_path, e));
+        }
+    }
+
+    if let Err(e) = generate_assets(&assets_path, report) {
+        report.error(e);
+    }
+    let layouts_path = PathBuf::from(LAYOUTS_FOLDER);
+    if !layouts_path.exists() {
+        if let Err(e) = fs::create_dir(&layouts_path) {
+            report.error(Error::io(&layouts_path, e));
+        }
     }
 
-    generate_assets(assets_path)
-        .unwrap_or_else(|e| eprintln!("Some error in \"assets\" folder. {e}"));
-    parse_nodes(liquid_tags, &mut parent.as_object().unwrap());
+    let mut pages = Vec::new();
+    collect_pages(&parent.as_object().unwrap(), &mut pages);
+
+    let templates = Templates::default();
+    render_pages(liquid_tags, &templates, config, report, &pages);
 
     Ok(())
 }
 
+fn parse_file(
+    plugins: &Plugins,
+    templates: &Templates,
+    config: &Config,
+    report: &BuildReport,
+    object: &Liquid,
+) -> Result<(), Error> {
+    let file = object.as_object().result(&()).with_path(SITE)?;
+
+    let path = file.get(PAGE_PATH).unwrap().as_string().result(&());
+    let path = path.with_path(SITE)?;
+    let content = file.get(PAGE_CONTENT).unwrap().as_string().result(&());
+    let content = content.with_path(path)?;
+
+    let out_path_rel = &path[SITE.len() + 1..];
+    let mut out_path = PathBuf::from(PAGE_OUT_FOLDER);
+    out_path.push(out_path_rel);
+
+    match out_path.to_str() {
+        Some(out_str) => out_path = PathBuf::from(out_str.replace(".md", ".html")),
+        None => {
+            let source = io::Error::new(io::ErrorKind::InvalidData, "The path is not UTF-8.");
+            report.error(Error::io(&out_path, source));
+            return Ok(());
+        }
+    }
+
+    let mut offset = 0;
+
+    let layout = match file.get(PAGE_LAYOUT) {
+        Some(s) => s.as_string().result(&()).with_path(path)?,
+        None => {
+            offset = file
+                .get(PAGE_FRONT_MATTER_OFFSET)
+                .expect("Created by application.")
+                .as_int()
+                .unwrap_or_default();
+
+            if offset != 0 {
+                offset += 3;
+            }
+
+            "paste_in"
+        } // TODO:(frac) get rid of str
+    };
+
+    fn walk_write(path: &Path, content: &str) -> Result<(), io::Error> {
+        if let Err(e) = fs::write(path, content) {
+            match e.kind() {
+                io::ErrorKind::NotFound => {
+                    let mut folder_path = path.to_path_buf();
+                    folder_path.pop();
+                    fs::DirBuilder::new().recursive(true).create(folder_path)?;
+                    return walk_write(path, content);
+                }
+                _ => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    let interrupt = Cell::new(None);
+    let scopes = Scopes::default();
+    let blocks = BlockOverrides::default();
+    let trace = RefCell::default();
+
+    let parsed = match layout {
+        "paste_in" => {
+            let state = LiquidState {
+                plugins,
+                file_path: path,
+                page_path: path,
+                current_line: 0,
+                current_column: 0,
+                interrupt: &interrupt,
+                templates,
+                config,
+                scopes: &scopes,
+                blocks: &blocks,
+                report,
+                trace: &trace,
+            };
+
+            parse(&state, object, content, offset as _).map_err(|e| Error::render(path, e))?
+        }
+        any => {
+            let state = LiquidState {
+                plugins,
+                file_path: &format!("layouts/{any}"),
+                page_path: path,
+                current_line: 0,
+                current_column: 0,
+                interrupt: &interrupt,
+                templates,
+                config,
+                scopes: &scopes,
+                blocks: &blocks,
+                report,
+                trace: &trace,
+            };
+
+            render_layouts(&state, object, any).map_err(|e| Error::render(path, e))?
+        }
+    };
+
+    // Undefined variables in strict mode are reported without stopping the
+    // render, the page is not written when it has any.
+    if report.error_count() > 0 {
+        return Ok(());
+    }
+
+    walk_write(&out_path, &parsed).with_path(&out_path)
+}
+
+/// Renders `pages` on one thread per core. Each page collects its problems
+/// in its own report, they are added to `report` in page order once every
+/// page is done.
+fn render_pages(
+    plugins: &Plugins,
+    templates: &Templates,
+    config: &Config,
+    report: &BuildReport,
+    pages: &[Liquid],
+) {
+    let reports: Vec<BuildReport> = pages.iter().map(|_| BuildReport::deferred()).collect();
+    let next_page = AtomicUsize::new(0);
+
+    let workers = thread::available_parallelism()
+        .map_or(1, |cores| cores.get())
+        .min(pages.len());
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let i = next_page.fetch_add(1, atomic::Ordering::Relaxed);
+                let Some(page) = pages.get(i) else {
+                    break;
+                };
+
+                if let Err(e) = parse_file(plugins, templates, config, &reports[i], page) {
+                    reports[i].error(e);
+                }
+            });
+        }
+    });
+
+    for page_report in reports {
+        report.append(page_report);
+    }
+}
+
+/// `layouts/<name>`, used by `layout` front matter and `extends`.
+pub fn get_layout(state: &LiquidState, name: &str) -> Result<Arc<Template>, ParseError> {
+    let layout_path = Path::new(LAYOUTS_FOLDER).join(name);
+
+    match state.templates.get(state, &layout_path) {
+        Ok(layout) => Ok(layout),
+        Err(e) => Err(format!("Layout `{name}` could not found. {e}").into()),
+    }
+}
+
+const LAYOUT: &str = "layout";
+const LAYOUT_CONTENT: &str = "content";
+
+/// Renders `layout` and then every layout named by the `layout` front matter
+/// of the previous one. Each layout sees its own front matter as `layout` and
+/// the result of the previous one as `content`.
+fn render_layouts(state: &LiquidState, page: &Liquid, layout: &str) -> Result<String, ParseError> {
+    let mut chain: Vec<String> = Vec::new();
+    let mut name = layout.to_string();
+    let mut content = None;
+
+    loop {
+        if chain.contains(&name) {
+            let cycle: Vec<String> = chain
+                .iter()
+                .chain([&name])
+                .map(|n| format!("`{n}`"))
+                .collect();
+            return Err(format!("Layout cycle {}.", cycle.join(" -> ")).into());
+        }
+
+        let layout = get_layout(state, &name)?;
+
+        let mut frame = Object::new();
+        frame.insert(LAYOUT.to_string(), layout.front_matter.clone());
+        if let Some(content) = content.take() {
+            frame.insert(LAYOUT_CONTENT.to_string(), Liquid::safe(content));
+        }
+
+        let rendered = state
+            .scopes
+            .with_frame(frame, || layout.render(state, page))
+            .map_err(|e| {
+                let page_path = state.get_key(page, PAGE_PATH).map(|p| p.to_output());
+                let through: Vec<String> = page_path
+                    .iter()
+                    .map(String::as_str)
+                    .chain(chain.iter().map(String::as_str))
+                    .chain([name.as_str()])
+                    .map(|n| format!("`{n}`"))
+                    .collect();
+                e.with_note(format!("in layout chain {}", through.join(" -> ")))
+            })?;
+
+        let next = layout
+            .front_matter
+            .as_object()
+            .and_then(|front_matter| front_matter.get(LAYOUT).map(|l| l.to_output()));
+
+        match next {
+            Some(next) => {
+                chain.push(name);
+                name = next;
+                content = Some(rendered);
+            }
+            None => return Ok(rendered),
+        }
+    }
+}
+
 fn get_front_matter_offset(text: &str) -> isize {
     let text_start = text.as_ptr();
     let mut text_end = text.as_ptr();
@@ -369,74 +584,88 @@ fn get_front_matter_offset(text: &str) -> isize {
     unsafe { text_end.offset_from(text_start) }
 }
 
-fn create_folder<P: AsRef<Path>>(path: P, parent_folder: MutRc<Object>) -> Result<(), io::Error> {
-    let new_folder = Liquid::default_object();
-
-    new_folder
-        .with_object(|object| {
-            object.insert(FOLDER_FIELDS_FOLDER.to_string(), Liquid::default_array());
-            object.insert(FOLDER_FIELDS_FILES.to_string(), Liquid::default_array());
-
-            object.insert(
-                PAGE_PARENT.to_string(),
-                LiquidInner::WeakObject(Rc::downgrade(&parent_folder.0)).into(),
-            );
-
-            Some(())
-        })
-        .unwrap();
-
-    parent_folder
-        .get(FOLDER_FIELDS_FOLDER)
-        .expect("folders not found")
-        .as_array()
-        .result(&())?
-        .push(new_folder.clone());
-
-    for e in fs::read_dir(path)? {
-        fn inner(e: Result<DirEntry, io::Error>, parent: MutRc<Object>) -> Result<(), io::Error> {
-            let e = e?;
-            let path = e.path();
+/// Reads the folder at `path` with its pages and sub folders, which link back
+/// to it as `parent`. Problems of single entries are added to `report`.
+fn create_folder(
+    path: &Path,
+    parent: Option<Weak<Object>>,
+    zone: Zone,
+    report: &BuildReport,
+) -> Result<Liquid, Error> {
+    let entries = fs::read_dir(path).with_path(path)?;
+
+    let folder = Arc::new_cyclic(|folder: &Weak<Object>| {
+        let mut folders = Array::new();
+        let mut files = Array::new();
+
+        for e in entries {
+            let entry = e.with_path(path).and_then(|e| {
+                let path = e.path();
+
+                match e.file_type().with_path(&path)?.is_dir() {
+                    true => {
+                        create_folder(&path, Some(folder.clone()), zone, report).map(|f| (true, f))
+                    }
+                    false => create_file(&path, folder.clone(), zone).map(|f| (false, f)),
+                }
+            });
 
-            match e.file_type()?.is_dir() {
-                true => create_folder(path, parent),
-                false => create_file(path, parent),
+            match entry {
+                Ok((true, child)) => folders.push(child),
+                Ok((false, child)) => files.push(child),
+                Err(e) => report.error(e),
             }
         }
 
-        match inner(e, new_folder.as_object().unwrap()) {
-            Ok(_) => (),
-            Err(e) => {
-                eprint!("{}", e);
-                continue;
-            }
+        let mut object = Object::new();
+        object.insert(FOLDER_FIELDS_FOLDER.to_string(), Arc::new(folders).into());
+        object.insert(FOLDER_FIELDS_FILES.to_string(), Arc::new(files).into());
+        if let Some(parent) = parent {
+            object.insert(PAGE_PARENT.to_string(), parent.into());
         }
-    }
 
-    Ok(())
+        object
+    });
+
+    Ok(folder.into())
 }
 
-pub fn yaml_to_liquid(yaml: Yaml) -> Liquid {
+/// Converts front matter, timestamps become dates in `zone` unless they
+/// carry their own offset.
+pub fn yaml_to_liquid(yaml: Yaml, zone: Zone) -> Liquid {
     match yaml {
-        Yaml::Real(s) => s.into(),
-        Yaml::Integer(i) => (i as i64).into(),
-        Yaml::String(s) => s.into(),
+        Yaml::Real(s) => match Yaml::Real(s.clone()).as_f64() {
+            Some(f) => f.into(),
+            None => s.into(),
+        },
+        Yaml::Integer(i) => i.into(),
+        Yaml::String(s) => match date::parse_date(&s, zone) {
+            Some(date) => date.into(),
+            None => s.into(),
+        },
         Yaml::Boolean(b) => b.into(),
         Yaml::Array(a) => {
             let mut l = Vec::new();
             for y in a {
-                l.push(yaml_to_liquid(y));
+                l.push(yaml_to_liquid(y, zone));
             }
-            MutRc(Rc::new(UnsafeCell::new(l)), false).into()
+            Arc::new(l).into()
         }
         Yaml::Hash(h) => {
             let mut hash = HashMap::new();
 
             for (k, v) in h {
-                hash.insert(k.as_str().unwrap().to_string(), yaml_to_liquid(v));
+                // Keys are looked up by name, `404: x` and `true: x` are `"404"` and `"true"`.
+                let key = match k {
+                    Yaml::String(s) | Yaml::Real(s) => s,
+                    Yaml::Integer(i) => i.to_string(),
+                    Yaml::Boolean(b) => b.to_string(),
+                    k => yaml_to_liquid(k, zone).to_output(),
+                };
+                hash.insert(key, yaml_to_liquid(v, zone));
             }
 
-            MutRc(Rc::new(UnsafeCell::new(hash)), false).into()
+            Arc::new(hash).into()
         }
         Yaml::Alias(a) => (a as i64).into(),
         Yaml::Null => ().into(),
@@ -444,41 +673,51 @@ pub fn yaml_to_liquid(yaml: Yaml) -> Liquid {
     }
 }
 
-fn create_file(path: PathBuf, parent: MutRc<Object>) -> Result<(), io::Error> {
-    let content: String = fs::read_to_string(&path)?;
-
-    let path_str = match path.to_str() {
-        Some(s) => s,
-        None => {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Path is not utf8",
-            ));
-        }
-    };
-
-    let front_matter_finish_index = get_front_matter_offset(&content) as usize;
+/// Parses the front matter of `content`, returns it with the offset of its
+/// closing `---`, which is `0` without front matter.
+pub fn parse_front_matter(
+    content: &str,
+    zone: Zone,
+) -> Result<(Liquid, usize), yaml_rust::ScanError> {
+    let front_matter_finish_index = get_front_matter_offset(content) as usize;
     let yaml_source = {
         let non_trimmed_yaml_source = &content[..front_matter_finish_index];
 
-        if non_trimmed_yaml_source.len() == 0 {
+        if non_trimmed_yaml_source.is_empty() {
             non_trimmed_yaml_source
         } else {
             &non_trimmed_yaml_source[3..non_trimmed_yaml_source.len()]
         }
     };
 
-    let front_matter = {
-        let mut yaml = match yaml_rust::YamlLoader::load_from_str(yaml_source) {
-            Ok(y) => y,
-            Err(e) => {
-                return Err(io::Error::new(io::ErrorKind::InvalidData, e));
-            }
-        };
+    let mut yaml = yaml_rust::YamlLoader::load_from_str(yaml_source)?;
+
+    let front_matter = match yaml.pop() {
+        Some(yaml) => yaml_to_liquid(yaml, zone),
+        None => Liquid::default_object(),
+    };
 
-        match yaml.pop() {
-            Some(yaml) => yaml_to_liquid(yaml),
-            None => Liquid::default_object(),
+    Ok((front_matter, front_matter_finish_index))
+}
+
+fn create_file(path: &Path, parent: Weak<Object>, zone: Zone) -> Result<Liquid, Error> {
+    let content: String = fs::read_to_string(path).with_path(path)?;
+
+    let path_str = match path.to_str() {
+        Some(s) => s,
+        None => {
+            let e = io::Error::new(io::ErrorKind::InvalidData, "Path is not utf8");
+            return Err(Error::io(path, e));
+        }
+    };
+
+    let (mut front_matter, front_matter_finish_index) = match parse_front_matter(&content, zone) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            return Err(Error::FrontMatter {
+                path: path_str.to_string(),
+                message: e.to_string(),
+            })
         }
     };
 
@@ -491,353 +730,187 @@ fn create_file(path: PathBuf, parent: MutRc<Object>) -> Result<(), io::Error> {
                 (front_matter_finish_index as i64).into(),
             );
 
-            front_matter.insert(
-                PAGE_PARENT.to_string(),
-                LiquidInner::WeakObject(Rc::downgrade(&parent.0)).into(),
-            );
-            Some(())
+            front_matter.insert(PAGE_PARENT.to_string(), parent.clone().into());
         })
-        .io_result()?;
-
-    parent
-        .get(FOLDER_FIELDS_FILES)
-        .io_result()?
-        .as_array()
-        .io_result()?
-        .push(front_matter);
+        .io_result()
+        .with_path(path)?;
 
-    Ok(())
+    Ok(front_matter)
 }
 
-#[derive(Debug)]
+/// Where an error happened or passed through on its way up.
+#[derive(Debug, Clone)]
 pub struct StackInfo {
-    info: String,
+    pub path: String,
+    pub line: usize,
+    pub column: usize,
+    /// The token as written, delimiters included.
+    pub source: String,
 }
 
 impl StackInfo {
-    pub fn new_info(state: &LiquidState, to_parse: &str, found: regex::Match<'_>) -> Self {
-        let (line, column) = get_line_and_column(to_parse, found.start());
-        let line = line + state.current_line;
+    pub fn new_info(state: &LiquidState, location: &Location) -> Self {
         Self {
-            info: format!(
-                "{path}:{line}:{column} {tag}",
-                path = state.file_path,
-                tag = found.as_str()
-            ),
+            path: state.file_path.to_string(),
+            line: location.line,
+            column: location.column,
+            source: location.source.clone(),
         }
     }
 }
 
 #[derive(Debug)]
 pub struct ParseError {
+    /// Innermost location first, followed by the tags, includes and layouts
+    /// it was rendered from.
     pub stack: Vec<StackInfo>,
     pub message: String,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
 }
 
-impl<'a> ParseError {
+impl ParseError {
     pub fn new(message: String) -> Self {
         ParseError {
             stack: Vec::new(),
             message,
+            notes: Vec::new(),
+            help: None,
         }
     }
 
-    pub fn new_in_parse<'s>(
-        message: String,
-        state: &LiquidState,
-        to_parse: &'s str,
-        found: regex::Match<'s>,
-    ) -> Self {
-        Self {
-            stack: vec![StackInfo::new_info(state, to_parse, found)],
-            message,
-        }
+    pub fn new_at(message: String, state: &LiquidState, location: &Location) -> Self {
+        Self::new(message).add_stack(state, location)
+    }
+
+    pub fn add_stack(mut self, state: &LiquidState, location: &Location) -> Self {
+        self.stack.push(StackInfo::new_info(state, location));
+        self
+    }
+
+    pub fn with_note(mut self, note: String) -> Self {
+        self.notes.push(note);
+        self
     }
 
-    pub fn add_stack(
-        mut self,
-        state: &LiquidState,
-        to_parse: &str,
-        found: regex::Match<'_>,
-    ) -> Self {
-        self.stack.push(StackInfo::new_info(state, to_parse, found));
+    pub fn with_help(mut self, help: String) -> Self {
+        self.help = Some(help);
         self
     }
 }
 
-impl<'a> From<String> for ParseError {
+impl From<String> for ParseError {
     fn from(value: String) -> Self {
         Self::new(value)
     }
 }
 
-impl<'a> Display for ParseError {
+impl Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        writeln!(f, "{message}", message = self.message)?;
-
-        for s in &self.stack {
-            writeln!(f, "\tat {}", s.info)?;
-        }
-
-        Ok(())
+        diagnostic::write_error(f, self)
     }
 }
 
 impl std::error::Error for ParseError {}
 
-impl Into<Error> for ParseError {
-    fn into(self) -> Error {
-        Error::new(io::ErrorKind::Other, self)
+impl From<ParseError> for io::Error {
+    fn from(error: ParseError) -> Self {
+        io::Error::other(error)
     }
 }
 
-lazy_static::lazy_static!(
-  pub static ref REG: regex::Regex = regex::Regex::new(r"\{(\{|%)[^\{,\n}]*(}|%)}").unwrap();
-);
-
-enum BlockState<'a> {
-    // Opened(regex::Match<'a>, LiquidTag<'a>, String),
-    Closed,
-
-    Opened {
-        block_opener: regex::Match<'a>,
-        block_opener_tag: &'a str,
-        block_opener_plugin: &'a str,
-        block_ender: String,
-    },
-}
-
 pub fn parse(
     state: &LiquidState,
     object: &Liquid,
     to_parse: &str,
     skip_offset: usize,
 ) -> Result<String, ParseError> {
-    let mut parsed: Vec<String> = Vec::new();
-
-    let mut internal_state = BlockState::Closed;
-    let mut last_found_end = skip_offset;
-    let mut request_trim_start = false;
-
-    let mut same_block_recursion = 0;
-
-    for found in REG.find_iter(to_parse) {
-        let non_trimmed_tag = found.as_str();
-        let len = non_trimmed_tag.len();
+    Template::compile(state, to_parse, skip_offset)?.render(state, object)
+}
 
-        let trim_start = non_trimmed_tag[2..3].contains('-');
-        let trim_end = non_trimmed_tag[len - 3..len - 2].contains('-');
+/// Text of an output, HTML escaped unless it is trusted or the template
+/// type is not auto-escaped.
+pub fn output_text(state: &LiquidState, output: &Liquid) -> String {
+    match output.is_safe() || !state.config.autoescapes(state.file_path) {
+        true => output.to_output(),
+        false => filters::escape_html(&output.to_output()),
+    }
+}
 
-        let tag = {
-            let start_offset = trim_start as usize;
-            let end_offset = trim_end as usize;
+/// Renders already parsed nodes, used by blocks to render their children.
+pub fn render_nodes(
+    state: &LiquidState,
+    object: &Liquid,
+    nodes: &[Node],
+) -> Result<String, ParseError> {
+    let mut parsed = String::new();
 
-            non_trimmed_tag[2 + start_offset..len - (2 + end_offset)].trim()
+    for node in nodes {
+        let location = match node {
+            Node::Text(text) => {
+                parsed.push_str(text);
+                continue;
+            }
+            Node::Output { location, .. }
+            | Node::Tag { location, .. }
+            | Node::Block { location, .. } => location,
         };
-        let plugin_name = tag.split(' ').next().unwrap().trim();
-
-        match internal_state {
-            BlockState::Closed => {
-                // ? SYNC:(non_matched_push)
-                {
-                    let mut non_matched_text = &to_parse[last_found_end..found.start()];
-                    last_found_end = found.end();
-
-                    if request_trim_start {
-                        non_matched_text = non_matched_text.trim_start();
-                    }
-
-                    if trim_end {
-                        non_matched_text = non_matched_text.trim_end();
-                    }
-
-                    parsed.push(non_matched_text.to_string());
-                    request_trim_start = trim_start;
-                }
-
-                match &non_trimmed_tag[..2] {
-                    "{{" => {
-                        let mut tag_without_filter = tag;
-
-                        let first_pipe = tag.find('|').unwrap_or_default();
-
-                        let filters: Vec<&str>;
-                        if first_pipe == 0 {
-                            filters = Vec::new()
-                        } else {
-                            tag_without_filter = &tag[..first_pipe].trim();
-                            filters = tag[first_pipe + 1..].split('|').map(|s| s.trim()).collect();
-                        }
-
-                        let mut target_object = match state.get_key(object, tag_without_filter) {
-                            Ok(s) => s,
-                            Err(e) => {
-                                return Err(e.add_stack(state, to_parse, found));
-                            }
-                        };
-
-                        for filter in filters {
-                            if let Some(filter_proc) = state.plugins.filters.get(filter) {
-                                match filter_proc(state, tag, &mut target_object) {
-                                    Ok(success) => target_object = success,
-                                    Err(e) => return Err(e.add_stack(state, to_parse, found)),
-                                };
-                            } else {
-                                return Err(ParseError::new_in_parse(
-                                    format!("Filter {filter} does not exists."),
-                                    state,
-                                    to_parse,
-                                    found,
-                                ));
-                            }
-                        }
-
-                        // ? SYNC:(output_parser)
-                        {
-                            let output = target_object.as_string().result(state)?;
-
-                            let mut output_not_trimmed = output.as_str();
-
-                            if request_trim_start {
-                                output_not_trimmed = output_not_trimmed.trim_start();
-                            }
-
-                            if trim_end {
-                                output_not_trimmed = output_not_trimmed.trim_end();
-                            }
-
-                            parsed.push(output_not_trimmed.to_string());
 
-                            request_trim_start = trim_start;
-                        }
-                    }
-                    "{%" => {
-                        if state.plugins.blocks.contains_key(plugin_name) {
-                            internal_state = BlockState::Opened {
-                                block_opener: found,
-                                block_opener_tag: tag,
-                                block_opener_plugin: plugin_name,
-                                block_ender: "end".to_string() + plugin_name,
-                            };
-                            continue;
-                        }
-
-                        if let Some(tag_proc) = state.plugins.tags.get(plugin_name) {
-                            let output = match tag_proc(state, object, tag) {
-                                Ok(success) => success,
-                                Err(e) => {
-                                    return Err(e.add_stack(state, to_parse, found));
-                                } // ! Handle error
-                            };
-
-                            // ? SYNC:(output_parser)
-                            {
-                                let output = output.as_string().result(state)?;
+        state
+            .trace
+            .borrow_mut()
+            .push(StackInfo::new_info(state, location));
+        let rendered = render_node(state, object, node);
+        state.trace.borrow_mut().pop();
 
-                                let mut output_not_trimmed = output.as_str();
-
-                                if request_trim_start {
-                                    output_not_trimmed = output_not_trimmed.trim_start();
-                                }
-
-                                if trim_end {
-                                    output_not_trimmed = output_not_trimmed.trim_end();
-                                }
-
-                                parsed.push(output_not_trimmed.to_string());
+        match rendered {
+            Ok(output) => parsed.push_str(&output),
+            Err(e) => return Err(e.add_stack(state, location)),
+        }
 
-                                request_trim_start = trim_start;
-                            }
+        if state.interrupt.get().is_some() {
+            break;
+        }
+    }
 
-                            continue;
-                        }
+    Ok(parsed)
+}
 
-                        return Err(ParseError::new_in_parse(
-                            format!("\"{plugin_name}\" does not exist in plugins."),
-                            state,
-                            to_parse,
-                            found,
-                        ));
-                    }
-                    _ => unreachable!(),
+fn render_node(state: &LiquidState, object: &Liquid, node: &Node) -> Result<String, ParseError> {
+    match node {
+        Node::Text(text) => Ok(text.clone()),
+        Node::Output { pipeline, .. } => Ok(output_text(state, &pipeline.evaluate(state, object)?)),
+        Node::Tag { name, markup, .. } => {
+            let tag_proc = match state.plugins.tags.get(name) {
+                Some(tag_proc) => tag_proc,
+                None => {
+                    let error = ParseError::new(format!("\"{name}\" does not exist in plugins."));
+                    let candidates = state.plugins.tags.keys().chain(state.plugins.blocks.keys());
+
+                    return Err(match diagnostic::suggestion(name, candidates) {
+                        Some(help) => error.with_help(help),
+                        None => error,
+                    });
                 }
-            }
-            BlockState::Opened {
-                block_opener,
-                block_opener_tag,
-                block_opener_plugin,
-                block_ender,
-            } => {
-                if plugin_name == block_ender {
-                    if same_block_recursion > 0 {
-                        same_block_recursion -= 1;
-                        internal_state = BlockState::Opened {
-                            block_opener,
-                            block_opener_tag,
-                            block_opener_plugin,
-                            block_ender,
-                        };
-                        continue;
-                    }
-
-                    internal_state = BlockState::Closed;
-                    last_found_end = found.end();
-
-                    let block_text = &to_parse[block_opener.end()..found.start()];
-
-                    let plugin_proc = state
-                        .plugins
-                        .blocks
-                        .get(block_opener_plugin)
-                        .expect("Checked while opening block.");
+            };
 
-                    let output = match plugin_proc(state, object, block_opener_tag, block_text) {
-                        Ok(success) => success,
-                        Err(e) => {
-                            return Err(e.add_stack(state, to_parse, found));
-                        }
-                    };
-
-                    // ? SYNC:(output_parser)
-                    {
-                        let output = output.as_string().result(state)?;
-
-                        let mut output_not_trimmed = output.as_str();
-
-                        if request_trim_start {
-                            output_not_trimmed = output_not_trimmed.trim_start();
-                        }
-
-                        if trim_end {
-                            output_not_trimmed = output_not_trimmed.trim_end();
-                        }
-
-                        parsed.push(output_not_trimmed.to_string());
-
-                        request_trim_start = trim_start;
-                    }
-                } else {
-                    if block_opener_plugin == plugin_name {
-                        same_block_recursion += 1;
-                    }
-                    internal_state = BlockState::Opened {
-                        block_opener,
-                        block_opener_tag,
-                        block_opener_plugin,
-                        block_ender,
-                    }
-                }
-            }
+            Ok(tag_proc(state, object, markup)?.to_output())
+        }
+        Node::Block {
+            name,
+            markup,
+            children,
+            ..
+        } => {
+            let plugin_proc = state
+                .plugins
+                .blocks
+                .get(name)
+                .expect("Checked while building the tree.");
+
+            Ok(plugin_proc(state, object, markup, children)?.to_output())
         }
     }
-
-    if request_trim_start {
-        parsed.push(to_parse[last_found_end..].trim_start().to_string()); // remaining text
-    } else {
-        parsed.push(to_parse[last_found_end..].to_string()); // remaining text
-    }
-
-    Ok(parsed.join(""))
 }
 
 pub fn get_object_path(state: &LiquidState, liquid: &Liquid) -> String {
@@ -850,13 +923,181 @@ pub fn get_object_path(state: &LiquidState, liquid: &Liquid) -> String {
     }
 }
 
-pub fn get_line_and_column(text: &str, match_start: usize) -> (usize, usize) {
-    let lines = match text.get(..match_start + 1) {
-        Some(s) => s.lines(),
-        None => text[..match_start].lines(),
-    };
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE_FILE: &str = "site/p.md";
+
+    /// Renders the page `site/p.md` with the layout `layout`, `files` are the
+    /// layouts and includes of the site.
+    fn render_page(
+        config: &str,
+        files: &[(&str, &str)],
+        content: &str,
+        layout: &str,
+    ) -> (Result<String, ParseError>, BuildReport) {
+        let config: Config = serde_yaml::from_str(config).unwrap();
+        let report = BuildReport::deferred();
+        let templates = Templates::default();
+        let layout_path = format!("{LAYOUTS_FOLDER}/{layout}");
+
+        let state = LiquidState {
+            plugins: &Plugins::new(),
+            file_path: &layout_path,
+            page_path: PAGE_FILE,
+            current_line: 0,
+            current_column: 0,
+            interrupt: &Cell::new(None),
+            templates: &templates,
+            config: &config,
+            scopes: &Scopes::default(),
+            blocks: &BlockOverrides::default(),
+            report: &report,
+            trace: &RefCell::default(),
+        };
+
+        for (path, source) in files {
+            templates.insert(&state, Path::new(path), source).unwrap();
+        }
+
+        let mut page = Object::new();
+        page.insert(PAGE_PATH.to_string(), PAGE_FILE.into());
+        page.insert(PAGE_CONTENT.to_string(), content.into());
+        page.insert(PAGE_FRONT_MATTER_OFFSET.to_string(), 0.into());
+
+        let rendered = render_layouts(&state, &Arc::new(page).into(), layout);
+        (rendered, report)
+    }
+
+    #[test]
+    fn undefined_variables_are_reported_for_the_page() {
+        let (rendered, report) = render_page(
+            "output: out\ntheme: default\nundefined: strict",
+            &[
+                (
+                    "layouts/post.html",
+                    "{% include r.html %}|{{ page.content }}",
+                ),
+                ("includes/r.html", "{{ page.zzz }}"),
+            ],
+            "{{ page.yyy }}text",
+            "post.html",
+        );
+
+        assert_eq!(rendered.unwrap(), "|<p>text</p>\n");
+        assert_eq!(report.paths(), [PAGE_FILE, PAGE_FILE]);
+    }
+
+    #[test]
+    fn unknown_filters_do_not_stop_strict_pages() {
+        let (rendered, report) = render_page(
+            "output: out\ntheme: default\nundefined: strict",
+            &[(
+                "layouts/post.html",
+                "{{ 'a' | upcas }}{% if page.zzz %}x{% endif %}",
+            )],
+            "",
+            "post.html",
+        );
+
+        assert_eq!(rendered.unwrap(), "a");
+        assert_eq!(report.error_count(), 2);
+        assert_eq!(report.paths(), [PAGE_FILE, PAGE_FILE]);
+    }
 
-    let line = lines.clone().count();
-    let column = lines.last().unwrap().len();
-    (line, column)
+    #[test]
+    fn layouts_are_chained() {
+        let (rendered, _) = render_page(
+            "output: out\ntheme: default",
+            &[
+                (
+                    "layouts/post.html",
+                    "---\nlayout: default.html\n---\n<article>{{ page.content }}</article>",
+                ),
+                (
+                    "layouts/default.html",
+                    "---\ntitle: Site\n---\n<body>{{ layout.title }}{{ content }}</body>",
+                ),
+            ],
+            "text",
+            "post.html",
+        );
+
+        assert_eq!(
+            rendered.unwrap(),
+            "\n<body>Site\n<article><p>text</p>\n</article></body>"
+        );
+    }
+
+    #[test]
+    fn layout_cycles_are_errors() {
+        let (rendered, _) = render_page(
+            "output: out\ntheme: default",
+            &[
+                ("layouts/a.html", "---\nlayout: b.html\n---\na"),
+                ("layouts/b.html", "---\nlayout: a.html\n---\nb"),
+            ],
+            "text",
+            "a.html",
+        );
+
+        let error = rendered.unwrap_err();
+        assert_eq!(
+            error.message,
+            "Layout cycle `a.html` -> `b.html` -> `a.html`."
+        );
+    }
+
+    #[test]
+    fn page_problems_are_reported_in_page_order() {
+        let config: Config = serde_yaml::from_str("output: out\ntheme: default").unwrap();
+        let report = BuildReport::deferred();
+
+        // Early pages take longest, so they finish last on most machines.
+        let paths: Vec<String> = (0..16).map(|i| format!("{SITE}/p{i}.md")).collect();
+        let pages: Vec<Liquid> = paths
+            .iter()
+            .enumerate()
+            .map(|(i, path)| {
+                let mut page = Object::new();
+                page.insert(PAGE_PATH.to_string(), path.as_str().into());
+                page.insert(
+                    PAGE_CONTENT.to_string(),
+                    format!(
+                        "{{% for i in (1..{}) %}}{{{{ i }}}}{{% endfor %}}{{% error %}}",
+                        (16 - i) * 200
+                    )
+                    .into(),
+                );
+                page.insert(PAGE_FRONT_MATTER_OFFSET.to_string(), 0.into());
+                Arc::new(page).into()
+            })
+            .collect();
+
+        render_pages(
+            &Plugins::new(),
+            &Templates::default(),
+            &config,
+            &report,
+            &pages,
+        );
+
+        assert_eq!(report.paths(), paths);
+    }
+
+    #[test]
+    fn front_matter_keys_are_strings() {
+        let (front_matter, _) = parse_front_matter(
+            "---\n404: missing\n1.5: half\ntrue: yes\n~: none\n[a, b]: list\ndate: x\n---\n",
+            Zone::UTC,
+        )
+        .unwrap();
+
+        let front_matter = front_matter.as_object().unwrap();
+        let mut keys: Vec<&str> = front_matter.keys().map(String::as_str).collect();
+        keys.sort();
+        assert_eq!(keys, ["", "1.5", "404", "ab", "date", "true"]);
+        assert_eq!(front_matter["404"], "missing".into());
+    }
 }
@@ -1,39 +1,131 @@
 use std::{
+    cell::Cell,
+    cmp::Ordering,
     collections::{hash_map::Keys, HashMap},
     ffi::OsStr,
     fmt, io,
-    path::PathBuf,
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
 use crate::{
-    helper::MutRef,
+    ast::{split_block, Node},
+    expression::{parse_named_arguments, parse_pipeline},
+    filters,
     liquid::Liquid,
-    liquid::{LiquidInner, MutRc, OptionToResult},
+    liquid::{LiquidInner, Object, OptionToResult},
     markdown::markdown,
-    parser::{parse, LiquidResult, LiquidState, PAGE_CONTENT, PAGE_FRONT_MATTER_OFFSET},
+    parser::{
+        output_text, parse, render_nodes, split_arguments, Interrupt, LiquidResult, LiquidState,
+        ParseError, PAGE_CONTENT, PAGE_FRONT_MATTER_OFFSET,
+    },
+    scope::Scopes,
+    template::BlockOverride,
 };
 
 pub type PfnLiquidFunction = fn(state: &LiquidState, object: &Liquid) -> LiquidResult;
 
 pub type PfnLiquidTag = fn(state: &LiquidState, object: &Liquid, tag: &str) -> LiquidResult;
 pub type PfnLiquidBlock =
-    fn(state: &LiquidState, object: &Liquid, tag: &str, block: &str) -> LiquidResult;
+    fn(state: &LiquidState, object: &Liquid, tag: &str, block: &[Node]) -> LiquidResult;
 pub type PfnLiquidFilter =
-    fn(state: &LiquidState, filter: &str, property: &mut Liquid) -> LiquidResult;
+    fn(state: &LiquidState, property: &Liquid, arguments: &FilterArguments) -> LiquidResult;
 pub type PfnLiquidAsset =
     fn(plugins: &Plugins, object: &Liquid, path: &OsStr) -> Result<(), io::Error>;
 
+/// Evaluated arguments of a filter, `filter: positional, key: keyword`.
+#[derive(Debug, Default)]
+pub struct FilterArguments {
+    pub positional: Vec<Liquid>,
+    pub keyword: Vec<(String, Liquid)>,
+}
+
+impl FilterArguments {
+    pub fn get(&self, index: usize) -> Option<&Liquid> {
+        self.positional.get(index)
+    }
+
+    pub fn get_keyword(&self, name: &str) -> Option<&Liquid> {
+        self.keyword.iter().find(|(k, _)| k == name).map(|(_, v)| v)
+    }
+}
+
 const LIQUID_TAGS: &[(&str, PfnLiquidTag)] = &[
     ("assign", pfn_assign),
     ("include", pfn_include),
+    ("render", pfn_render),
+    ("extends", pfn_extends),
+    ("cycle", pfn_cycle),
+    ("increment", pfn_increment),
+    ("decrement", pfn_decrement),
+    ("echo", pfn_echo),
     ("error", pfn_error),
+    ("break", pfn_break),
+    ("continue", pfn_continue),
 ];
 const LIQUID_BLOCKS: &[(&str, PfnLiquidBlock)] = &[
     ("capture", pfn_capture),
     ("if", pfn_if_block),
-    ("comment", pfn_comment),
+    ("unless", pfn_unless_block),
+    ("case", pfn_case_block),
+    ("for", pfn_for_block),
+    ("block", pfn_block),
+    ("tablerow", pfn_tablerow_block),
+];
+const LIQUID_FILTERS: &[(&str, PfnLiquidFilter)] = &[
+    ("dbg", pfn_dbg),
+    ("upcase", filters::pfn_upcase),
+    ("downcase", filters::pfn_downcase),
+    ("capitalize", filters::pfn_capitalize),
+    ("strip", filters::pfn_strip),
+    ("lstrip", filters::pfn_lstrip),
+    ("rstrip", filters::pfn_rstrip),
+    ("append", filters::pfn_append),
+    ("prepend", filters::pfn_prepend),
+    ("replace", filters::pfn_replace),
+    ("replace_first", filters::pfn_replace_first),
+    ("remove", filters::pfn_remove),
+    ("remove_first", filters::pfn_remove_first),
+    ("split", filters::pfn_split),
+    ("slice", filters::pfn_slice),
+    ("truncate", filters::pfn_truncate),
+    ("truncatewords", filters::pfn_truncatewords),
+    ("newline_to_br", filters::pfn_newline_to_br),
+    ("strip_newlines", filters::pfn_strip_newlines),
+    ("url_encode", filters::pfn_url_encode),
+    ("url_decode", filters::pfn_url_decode),
+    ("first", filters::pfn_first),
+    ("last", filters::pfn_last),
+    ("join", filters::pfn_join),
+    ("map", filters::pfn_map),
+    ("where", filters::pfn_where),
+    ("sort", filters::pfn_sort),
+    ("sort_natural", filters::pfn_sort_natural),
+    ("reverse", filters::pfn_reverse),
+    ("uniq", filters::pfn_uniq),
+    ("compact", filters::pfn_compact),
+    ("concat", filters::pfn_concat),
+    ("size", filters::pfn_size),
+    ("sum", filters::pfn_sum),
+    ("group_by", filters::pfn_group_by),
+    ("plus", filters::pfn_plus),
+    ("minus", filters::pfn_minus),
+    ("times", filters::pfn_times),
+    ("divided_by", filters::pfn_divided_by),
+    ("modulo", filters::pfn_modulo),
+    ("abs", filters::pfn_abs),
+    ("ceil", filters::pfn_ceil),
+    ("floor", filters::pfn_floor),
+    ("round", filters::pfn_round),
+    ("at_least", filters::pfn_at_least),
+    ("at_most", filters::pfn_at_most),
+    ("date", filters::pfn_date),
+    ("date_to_xmlschema", filters::pfn_date_to_xmlschema),
+    ("date_to_rfc822", filters::pfn_date_to_rfc822),
+    ("escape", filters::pfn_escape),
+    ("escape_once", filters::pfn_escape_once),
+    ("raw", filters::pfn_raw),
 ];
-const LIQUID_FILTERS: &[(&str, PfnLiquidFilter)] = &[("dbg", pfn_dbg)];
 const LIQUID_FUNCTIONS: &[(&str, PfnLiquidFunction)] = &[
     ("content", pfn_content),
     ("len", pfn_len),
@@ -109,58 +201,313 @@ impl Plugins {
     }
 }
 
-fn pfn_assign<'a>(state: &LiquidState, object: &Liquid, tag: &str) -> LiquidResult {
-    let tags: Vec<&str> = tag.split(' ').map(|s| s.trim()).collect();
-    let target = tags[1];
-    let from = tags[3];
-
-    let mut r = object.as_object().result(state)?;
-    let i = state.get_key(object, from)?;
+// {% assign a = b | filter: c %}
+fn pfn_assign(state: &LiquidState, object: &Liquid, tag: &str) -> LiquidResult {
+    let (target, from) = match tag["assign".len()..].split_once('=') {
+        Some((target, from)) => (target.trim(), from),
+        None => return Err(format!("Invalid arguments {:?}", tag).into()),
+    };
 
-    r.insert(target.to_string(), i);
+    let value = parse_pipeline(from)?.evaluate(state, object)?;
+    state.scopes.assign(target, value);
 
     Ok(().into())
 }
 
-fn pfn_include<'a>(state: &LiquidState, object: &Liquid, tag: &str) -> LiquidResult {
-    let a: Vec<&str> = tag.split(" ").collect();
-    let include_file = a.last().unwrap().trim();
+// {% cycle "odd", "even" %}, {% cycle "group": "odd", "even" %}
+fn pfn_cycle(state: &LiquidState, object: &Liquid, tag: &str) -> LiquidResult {
+    let markup = tag.trim_start().strip_prefix("cycle").unwrap_or(tag).trim();
+
+    let (group, values) = match split_unquoted(markup, ':').as_slice() {
+        [values] => (values.trim().to_string(), *values),
+        [group, values] => (state.get_value(object, group.trim())?.to_output(), *values),
+        _ => return Err(format!("Invalid arguments {tag:?}").into()),
+    };
+
+    let values: Vec<&str> = split_unquoted(values, ',')
+        .into_iter()
+        .map(|v| v.trim())
+        .collect();
+    if values.iter().any(|v| v.is_empty()) {
+        return Err(format!("Invalid arguments {tag:?}, expected values.").into());
+    }
+
+    let index = state.scopes.cycle(&group, values.len());
+    let value = state.get_value(object, values[index])?;
+
+    Ok(Liquid::safe(output_text(state, &value)))
+}
+
+/// Splits on every `separator` outside of quotes.
+fn split_unquoted(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut quote = None;
+
+    for (i, c) in text.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => (),
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == separator => {
+                parts.push(&text[start..i]);
+                start = i + c.len_utf8();
+            }
+            None => (),
+        }
+    }
+
+    parts.push(&text[start..]);
+    parts
+}
+
+// {% increment counter %}
+fn pfn_increment(state: &LiquidState, _: &Liquid, tag: &str) -> LiquidResult {
+    match split_arguments(tag).as_slice() {
+        [_, name] => Ok(state.scopes.increment(name).into()),
+        _ => Err(format!("Invalid arguments {tag:?}").into()),
+    }
+}
+
+// {% decrement counter %}
+fn pfn_decrement(state: &LiquidState, _: &Liquid, tag: &str) -> LiquidResult {
+    match split_arguments(tag).as_slice() {
+        [_, name] => Ok(state.scopes.decrement(name).into()),
+        _ => Err(format!("Invalid arguments {tag:?}").into()),
+    }
+}
+
+// {% echo value | filter %}, mostly used inside {% liquid %}
+fn pfn_echo(state: &LiquidState, object: &Liquid, tag: &str) -> LiquidResult {
+    let markup = tag.trim_start().strip_prefix("echo").unwrap_or(tag);
+    let value = parse_pipeline(markup)?.evaluate(state, object)?;
+
+    Ok(Liquid::safe(output_text(state, &value)))
+}
+
+const INCLUDE: &str = "include";
+
+// {% include card.html title: page.title %}, {% include "my card.html" %}, {% include {{ widget }} %}
+fn pfn_include(state: &LiquidState, object: &Liquid, tag: &str) -> LiquidResult {
+    let markup = tag.trim_start().strip_prefix(INCLUDE).unwrap_or(tag).trim();
+
+    let (include_file, parameters) = split_include_name(state, object, markup)?;
+    let include_file = include_file.as_str();
+    if include_file.is_empty() {
+        return Err(format!("Invalid arguments {tag:?}, expected a file name.").into());
+    }
+
+    let path = include_path(include_file);
+
+    let mut include = Object::new();
+    for (name, value) in parse_named_arguments(parameters)? {
+        include.insert(name, value.evaluate(state, object)?);
+    }
+
+    let template = state.templates.get(state, &path)?;
+
+    let mut frame = Object::new();
+    frame.insert(INCLUDE.to_string(), Arc::new(include).into());
+
+    let rendered = state
+        .scopes
+        .with_frame(frame, || template.render(state, object))?;
+
+    Ok(rendered.into())
+}
+
+/// `includes/<include_file>`, or the file itself when it starts with `./`.
+///
+/// A missing file is reported when the template is read.
+fn include_path(include_file: &str) -> PathBuf {
+    match include_file.starts_with("./") {
+        true => PathBuf::from(include_file),
+        false => Path::new("includes").join(include_file),
+    }
+}
+
+// Handled by `Template::render` when it is at the top level.
+fn pfn_extends(_: &LiquidState, _: &Liquid, tag: &str) -> LiquidResult {
+    Err(format!("{tag:?} must be at the top level of a template.").into())
+}
+
+const BLOCK: &str = "block";
+
+// {% block sidebar %} default {% endblock %}, overrides see the overridden block as `block.super`.
+fn pfn_block(
+    state: &LiquidState,
+    object: &Liquid,
+    tag: &str,
+    block_contents: &[Node],
+) -> LiquidResult {
+    let name = match split_arguments(tag).as_slice() {
+        [_, name] => *name,
+        _ => return Err(format!("Invalid arguments {tag:?}, expected a block name.").into()),
+    };
+
+    let mut chain = match state.blocks.blocks.get(name) {
+        Some(overrides) => overrides.clone(),
+        None => Vec::new(),
+    };
+    chain.push(BlockOverride {
+        path: state.file_path,
+        nodes: block_contents,
+    });
+
+    Ok(Liquid::safe(render_block_chain(
+        state, object, name, &chain,
+    )?))
+}
+
+/// Renders the first block of `chain`, the rest is its `block.super`.
+fn render_block_chain(
+    state: &LiquidState,
+    object: &Liquid,
+    name: &str,
+    chain: &[BlockOverride],
+) -> Result<String, ParseError> {
+    let (block, rest) = match chain.split_first() {
+        Some(split) => split,
+        None => return Ok(String::new()),
+    };
+
+    let block_super = render_block_chain(state, object, name, rest)?;
+
+    let mut block_object = Object::new();
+    block_object.insert("name".to_string(), name.into());
+    block_object.insert("super".to_string(), Liquid::safe(block_super));
+
+    let mut frame = Object::new();
+    frame.insert(BLOCK.to_string(), Arc::new(block_object).into());
 
-    let mut new_state = LiquidState {
-        file_path: "",
-        current_line: 0,
+    let state = LiquidState {
+        file_path: block.path,
         ..*state
     };
 
-    let mut path = PathBuf::from("includes");
+    state
+        .scopes
+        .with_frame(frame, || render_nodes(&state, object, block.nodes))
+}
+
+const RENDER: &str = "render";
+
+// {% render "card.html", title: page.title %}, {% render "card.html" with product as item %},
+// {% render "card.html" for products as product %}
+fn pfn_render(state: &LiquidState, object: &Liquid, tag: &str) -> LiquidResult {
+    let markup = tag.trim_start().strip_prefix(RENDER).unwrap_or(tag).trim();
+
+    let (render_file, arguments) = split_include_name(state, object, markup)?;
+    if render_file.is_empty() {
+        return Err(format!("Invalid arguments {tag:?}, expected a file name.").into());
+    }
 
-    let s = if include_file.starts_with("./") {
-        new_state.file_path = include_file;
-        match std::fs::read_to_string(include_file) {
-            Ok(s) => s,
-            Err(e) => return Err(format!("{include_file} is not exists! {e}").into()),
+    let template = state.templates.get(state, &include_path(&render_file))?;
+
+    // `with`/`for` come before the first comma, named parameters after it.
+    let arguments = arguments.trim();
+    let (clause, parameters) = split_first_comma(arguments);
+
+    let default_alias = Path::new(&render_file)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let (clause, parameters) = match split_arguments(clause).as_slice() {
+        [keyword @ ("with" | "for"), value] => ((*keyword, *value, default_alias), parameters),
+        [keyword @ ("with" | "for"), value, "as", alias] => {
+            ((*keyword, *value, alias.to_string()), parameters)
         }
-    } else {
-        if !path.exists() {
-            match std::fs::create_dir(&path) {
-                Ok(_) => (),
-                Err(e) => return Err(format!("{e}").into()),
-            }
+        [keyword @ ("with" | "for"), ..] => {
+            return Err(format!(
+                "Invalid `{keyword}` in {tag:?}, expected `{keyword} value as name`."
+            )
+            .into())
+        }
+        _ => (("", "", default_alias), arguments.trim_start_matches(',')),
+    };
+
+    let mut global = Object::new();
+    for (name, value) in parse_named_arguments(parameters)? {
+        global.insert(name, value.evaluate(state, object)?);
+    }
+
+    // Only the parameters are visible, neither the page nor the caller's variables.
+    let render_isolated = |global: Object| {
+        let scopes = Scopes::new(global);
+        let interrupt = Cell::new(None);
+        let isolated = LiquidState {
+            scopes: &scopes,
+            interrupt: &interrupt,
+            ..*state
+        };
 
-            return Err(format!("{include_file} is not exists!").into());
+        template.render(&isolated, &Liquid::default_object())
+    };
+
+    let (keyword, value, alias) = clause;
+    match keyword {
+        "with" => {
+            global.insert(alias, state.get_value(object, value)?);
+            Ok(render_isolated(global)?.into())
         }
+        "for" => {
+            let items = loop_items(&state.get_value(object, value)?);
+            let length = items.len();
 
-        path.push(include_file);
+            let mut output = String::new();
+            for (index, item) in items.into_iter().enumerate() {
+                let mut frame = global.clone();
+                frame.insert(alias.clone(), item);
+                frame.insert(FOR_LOOP.to_string(), for_loop_object(index, length, None));
 
-        new_state.file_path = path.to_str().unwrap_or(include_file);
+                output.push_str(&render_isolated(frame)?);
+            }
 
-        match std::fs::read_to_string(&path) {
-            Ok(s) => s,
-            Err(e) => return Err(format!("{include_file} is not exists! {e}").into()),
+            Ok(output.into())
         }
-    };
+        _ => Ok(render_isolated(global)?.into()),
+    }
+}
 
-    Ok(parse(&new_state, object, &s, 0)?.into())
+/// Splits on the first comma outside of quotes.
+fn split_first_comma(text: &str) -> (&str, &str) {
+    let first = split_unquoted(text, ',')[0];
+
+    match text.get(first.len() + 1..) {
+        Some(rest) => (first, rest),
+        None => (text, ""),
+    }
+}
+
+/// Splits the file name off the markup of `include`, it is either a bare
+/// path, a quoted path that may contain spaces or a `{{ expression }}`.
+fn split_include_name<'m>(
+    state: &LiquidState,
+    object: &Liquid,
+    markup: &'m str,
+) -> Result<(String, &'m str), ParseError> {
+    if let Some(inner) = markup.strip_prefix("{{") {
+        let end = match inner.find("}}") {
+            Some(end) => end,
+            None => return Err(format!("Unterminated `{{{{` in {markup:?}").into()),
+        };
+
+        let name = parse_pipeline(&inner[..end])?.evaluate(state, object)?;
+        return Ok((name.to_output().trim().to_string(), &inner[end + 2..]));
+    }
+
+    if let Some(quote) = markup.chars().next().filter(|c| matches!(c, '"' | '\'')) {
+        let end = match markup[1..].find(quote) {
+            Some(end) => end + 1,
+            None => return Err(format!("Unterminated string in {markup:?}").into()),
+        };
+
+        return Ok((markup[1..end].to_string(), &markup[end + 1..]));
+    }
+
+    let end = markup.find(char::is_whitespace).unwrap_or(markup.len());
+    Ok((markup[..end].to_string(), &markup[end..]))
 }
 
 fn pfn_error(_: &LiquidState, _: &Liquid, tag: &str) -> LiquidResult {
@@ -172,7 +519,7 @@ fn pfn_capture(
     state: &LiquidState,
     object: &Liquid,
     tag: &str,
-    block_contents: &str,
+    block_contents: &[Node],
 ) -> LiquidResult {
     let tag_s: Vec<&str> = tag.trim().split(" ").map(|s| s.trim()).collect();
     let tag_name = match tag_s.get(1) {
@@ -180,43 +527,476 @@ fn pfn_capture(
         None => return Err(format!("Invalid arguments {:?}", tag).into()),
     };
 
-    let parsed_contents = parse(state, object, block_contents, 0)?;
+    let parsed_contents = render_nodes(state, object, block_contents)?;
 
-    let binding = object.as_object().unwrap();
-    let f = binding.get_mut();
-    f.insert(tag_name.to_string(), parsed_contents.into());
+    state.scopes.assign(tag_name, Liquid::safe(parsed_contents));
 
     Ok(().into())
 }
 
-fn pfn_comment(_: &LiquidState, _: &Liquid, _: &str, block_contents: &str) -> LiquidResult {
-    Ok(block_contents.into())
+// {% if a == b and c %} ... {% elsif d %} ... {% else %} ... {% endif %}
+fn pfn_if_block(
+    state: &LiquidState,
+    object: &Liquid,
+    tag: &str,
+    block_contents: &[Node],
+) -> LiquidResult {
+    conditional_block(state, object, tag, block_contents, false)
+}
+
+// {% unless a %} ... {% else %} ... {% endunless %}
+fn pfn_unless_block(
+    state: &LiquidState,
+    object: &Liquid,
+    tag: &str,
+    block_contents: &[Node],
+) -> LiquidResult {
+    conditional_block(state, object, tag, block_contents, true)
 }
 
-#[deprecated]
-fn pfn_if_block(
+fn conditional_block(
     state: &LiquidState,
     object: &Liquid,
     tag: &str,
-    block_contents: &str,
+    block_contents: &[Node],
+    negate: bool,
 ) -> LiquidResult {
-    let tokens: Vec<&str> = tag.trim().split(' ').map(|s| s.trim()).collect();
+    let branches = split_block(block_contents, &["elsif", "else"]);
 
-    if tokens.len() != 2 {
-        return Err(format!("TODO! {}", tokens.len()).into());
+    for (i, (branch_tag, body)) in branches.into_iter().enumerate() {
+        let branch_tag = if i == 0 { tag } else { branch_tag };
+
+        let matched = match split_arguments(branch_tag).as_slice() {
+            ["else"] => true,
+            [_, condition @ ..] if !condition.is_empty() => {
+                evaluate_condition(state, object, condition)? != (i == 0 && negate)
+            }
+            _ => return Err(format!("Invalid condition {branch_tag:?}").into()),
+        };
+
+        if matched {
+            return Ok(render_nodes(state, object, body)?.into());
+        }
     }
 
-    let condition = state.get_key(object, tokens[1])?;
+    Ok(().into())
+}
+
+// {% case a %} {% when "b", "c" %} ... {% when "d" or "e" %} ... {% else %} ... {% endcase %}
+fn pfn_case_block(
+    state: &LiquidState,
+    object: &Liquid,
+    tag: &str,
+    block_contents: &[Node],
+) -> LiquidResult {
+    let value = match split_arguments(tag).as_slice() {
+        [_, value] => Operand::new(state, object, value)?,
+        _ => return Err(format!("Invalid arguments {:?}", tag).into()),
+    };
 
-    if !condition.is() {
-        return Ok(().into());
+    let mut output = String::new();
+    let mut matched = false;
+
+    // Text before the first `when` is never rendered.
+    for (branch_tag, body) in split_block(block_contents, &["when", "else"])
+        .into_iter()
+        .skip(1)
+    {
+        let arguments = split_arguments(branch_tag);
+
+        let render = match arguments.as_slice() {
+            ["else"] => !matched,
+            ["when", values @ ..] if !values.is_empty() => {
+                let mut any = false;
+                for candidate in when_values(values) {
+                    if value.equals(&Operand::new(state, object, candidate)?) {
+                        any = true;
+                        break;
+                    }
+                }
+                any
+            }
+            _ => return Err(format!("Invalid arguments {branch_tag:?}").into()),
+        };
+
+        if render {
+            matched = true;
+            output.push_str(&render_nodes(state, object, body)?);
+        }
     }
 
-    Ok(parse(state, object, block_contents, 0)?.into())
+    Ok(output.into())
 }
 
-fn pfn_dbg(s: &LiquidState, _full_tag: &str, prop: &mut Liquid) -> LiquidResult {
-    pfn_dbg_fn(s, &prop)
+/// Splits `when` arguments separated by commas or `or`.
+fn when_values<'t>(arguments: &[&'t str]) -> Vec<&'t str> {
+    let mut values: Vec<&str> = arguments
+        .iter()
+        .flat_map(|argument| split_unquoted(argument, ','))
+        .collect();
+
+    values.retain(|v| !v.is_empty() && *v != "or");
+    values
+}
+
+enum Operand {
+    Value(Liquid),
+    Empty,
+    Blank,
+}
+
+impl Operand {
+    fn new(state: &LiquidState, object: &Liquid, expression: &str) -> Result<Self, ParseError> {
+        Ok(match expression {
+            "empty" => Operand::Empty,
+            "blank" => Operand::Blank,
+            expression => Operand::Value(state.get_value(object, expression)?),
+        })
+    }
+
+    fn equals(&self, other: &Operand) -> bool {
+        match (self, other) {
+            (Operand::Value(a), Operand::Value(b)) => a == b,
+            (Operand::Value(v), Operand::Empty) | (Operand::Empty, Operand::Value(v)) => {
+                v.is_empty()
+            }
+            (Operand::Value(v), Operand::Blank) | (Operand::Blank, Operand::Value(v)) => {
+                v.is_blank()
+            }
+            (Operand::Empty, Operand::Empty) | (Operand::Blank, Operand::Blank) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Evaluates `and`/`or` chains right to left, like Liquid does:
+/// `a or b and c` is `a or (b and c)` and `a and b or c` is `a and (b or c)`.
+pub fn evaluate_condition(
+    state: &LiquidState,
+    object: &Liquid,
+    tokens: &[&str],
+) -> Result<bool, ParseError> {
+    if let Some(i) = tokens.iter().position(|t| *t == "and" || *t == "or") {
+        let left = evaluate_comparison(state, object, &tokens[..i])?;
+        let right = evaluate_condition(state, object, &tokens[i + 1..])?;
+
+        return Ok(match tokens[i] {
+            "and" => left && right,
+            _ => left || right,
+        });
+    }
+
+    evaluate_comparison(state, object, tokens)
+}
+
+fn evaluate_comparison(
+    state: &LiquidState,
+    object: &Liquid,
+    tokens: &[&str],
+) -> Result<bool, ParseError> {
+    let (left, operator, right) = match tokens {
+        [value] => {
+            return Ok(match Operand::new(state, object, value)? {
+                Operand::Value(v) => v.is_truthy(),
+                _ => true,
+            })
+        }
+        [left, operator, right] => (
+            Operand::new(state, object, left)?,
+            *operator,
+            Operand::new(state, object, right)?,
+        ),
+        _ => return Err(format!("Invalid condition {:?}", tokens.join(" ")).into()),
+    };
+
+    match operator {
+        "==" => return Ok(left.equals(&right)),
+        "!=" | "<>" => return Ok(!left.equals(&right)),
+        _ => (),
+    }
+
+    let (left, right) = match (left, right) {
+        (Operand::Value(left), Operand::Value(right)) => (left, right),
+        _ => return Err(format!("Operator {operator} needs values to compare").into()),
+    };
+
+    if operator == "contains" {
+        if let Some(s) = left.as_string() {
+            return Ok(s.contains(&right.to_output()));
+        }
+
+        if let Some(a) = left.as_array() {
+            return Ok(a.contains(&right));
+        }
+
+        if let Some(o) = left.as_object() {
+            return Ok(o.contains_key(&right.to_output()));
+        }
+
+        return Ok(false);
+    }
+
+    let matches: fn(Ordering) -> bool = match operator {
+        "<" => Ordering::is_lt,
+        ">" => Ordering::is_gt,
+        "<=" => Ordering::is_le,
+        ">=" => Ordering::is_ge,
+        _ => return Err(format!("Unknown operator {operator}").into()),
+    };
+
+    // Like Liquid, values that can not be ordered, nil or mixed types, never match.
+    Ok(left.partial_cmp(&right).is_some_and(matches))
+}
+
+fn pfn_break(state: &LiquidState, _: &Liquid, _: &str) -> LiquidResult {
+    state.interrupt.set(Some(Interrupt::Break));
+    Ok(().into())
+}
+
+fn pfn_continue(state: &LiquidState, _: &Liquid, _: &str) -> LiquidResult {
+    state.interrupt.set(Some(Interrupt::Continue));
+    Ok(().into())
+}
+
+const FOR_LOOP: &str = "forloop";
+
+// {% for item in collection limit: 2 offset: 1 reversed %}
+fn pfn_for_block(
+    state: &LiquidState,
+    object: &Liquid,
+    tag: &str,
+    block_contents: &[Node],
+) -> LiquidResult {
+    let (item_name, _, items) = parse_loop(state, object, "for", tag)?;
+
+    let branches = split_block(block_contents, &["else"]);
+    let body = branches[0].1;
+
+    if items.is_empty() {
+        return match branches.get(1) {
+            Some((_, else_body)) => Ok(render_nodes(state, object, else_body)?.into()),
+            None => Ok(().into()),
+        };
+    }
+
+    let parent_loop = state.scopes.get(FOR_LOOP);
+
+    let output = state.scopes.with_frame(Object::new(), || {
+        render_loop(state, object, item_name, items, parent_loop, body)
+    })?;
+
+    Ok(output.into())
+}
+
+fn render_loop(
+    state: &LiquidState,
+    object: &Liquid,
+    item_name: &str,
+    items: Vec<Liquid>,
+    parent_loop: Option<Liquid>,
+    body: &[Node],
+) -> Result<String, ParseError> {
+    let length = items.len();
+    let mut output = String::new();
+
+    for (index, item) in items.into_iter().enumerate() {
+        let for_loop = for_loop_object(index, length, parent_loop.as_ref());
+
+        state.scopes.set(item_name, item);
+        state.scopes.set(FOR_LOOP, for_loop);
+
+        output.push_str(&render_nodes(state, object, body)?);
+
+        if state.interrupt.take() == Some(Interrupt::Break) {
+            break;
+        }
+    }
+
+    Ok(output)
+}
+
+/// `item in collection options` of a `for` or `tablerow` tag, the name of
+/// the item, the options and the items they select.
+fn parse_loop<'t>(
+    state: &LiquidState,
+    object: &Liquid,
+    block: &str,
+    tag: &'t str,
+) -> Result<(&'t str, LoopOptions, Vec<Liquid>), ParseError> {
+    let tokens: Vec<&str> = tag.split_whitespace().collect();
+
+    let (item_name, collection_key) = match tokens.as_slice() {
+        [_, item_name, "in", collection_key, ..] => (*item_name, *collection_key),
+        _ => return Err(format!("Invalid arguments {:?}", tag).into()),
+    };
+
+    let options = LoopOptions::parse(state, object, block, &tokens[4..])?;
+    let items = options.apply(loop_items(&state.get_value(object, collection_key)?));
+
+    Ok((item_name, options, items))
+}
+
+/// `limit:`, `offset:` and `reversed` of `for`, `cols:` of `tablerow`.
+#[derive(Debug, Default)]
+struct LoopOptions {
+    limit: Option<usize>,
+    offset: usize,
+    reversed: bool,
+    cols: Option<usize>,
+}
+
+impl LoopOptions {
+    fn parse(
+        state: &LiquidState,
+        object: &Liquid,
+        block: &str,
+        tokens: &[&str],
+    ) -> Result<Self, ParseError> {
+        let mut loop_options = Self::default();
+
+        let mut options = tokens.iter();
+        while let Some(option) = options.next() {
+            let (name, value) = match option.split_once(':') {
+                Some((name, "")) => (name, options.next().copied()),
+                Some((name, value)) => (name, Some(value)),
+                None => (*option, None),
+            };
+
+            match (block, name, value) {
+                ("for", "reversed", None) => loop_options.reversed = true,
+                ("tablerow", "cols", Some(value)) => {
+                    loop_options.cols = Some(loop_option_value(state, object, value)?)
+                }
+                (_, "limit", Some(value)) => {
+                    loop_options.limit = Some(loop_option_value(state, object, value)?)
+                }
+                (_, "offset", Some(value)) => {
+                    loop_options.offset = loop_option_value(state, object, value)?
+                }
+                _ => return Err(format!("Invalid {block} loop option {option:?}").into()),
+            }
+        }
+
+        Ok(loop_options)
+    }
+
+    fn apply(&self, items: Vec<Liquid>) -> Vec<Liquid> {
+        let mut items: Vec<Liquid> = items
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect();
+
+        if self.reversed {
+            items.reverse();
+        }
+
+        items
+    }
+}
+
+const TABLEROW_LOOP: &str = "tablerowloop";
+
+// {% tablerow item in collection cols: 3 limit: 6 offset: 1 %}
+fn pfn_tablerow_block(
+    state: &LiquidState,
+    object: &Liquid,
+    tag: &str,
+    block_contents: &[Node],
+) -> LiquidResult {
+    let (item_name, options, items) = parse_loop(state, object, "tablerow", tag)?;
+
+    let length = items.len();
+    let cols = options.cols.unwrap_or(length).max(1);
+
+    let render_rows = || -> Result<String, ParseError> {
+        let mut output = String::from("<tr class=\"row1\">\n");
+
+        for (index, item) in items.into_iter().enumerate() {
+            let col = index % cols;
+            let row = index / cols;
+            let last = index + 1 == length;
+            let col_last = col + 1 == cols || last;
+
+            let mut table_row_loop = for_loop_object(index, length, None);
+            table_row_loop.with_object(|table_row_loop| {
+                table_row_loop.insert("col".to_string(), (col as i64 + 1).into());
+                table_row_loop.insert("col0".to_string(), (col as i64).into());
+                table_row_loop.insert("col_first".to_string(), (col == 0).into());
+                table_row_loop.insert("col_last".to_string(), col_last.into());
+                table_row_loop.insert("row".to_string(), (row as i64 + 1).into());
+            });
+
+            state.scopes.set(item_name, item);
+            state.scopes.set(TABLEROW_LOOP, table_row_loop);
+
+            output.push_str(&format!("<td class=\"col{}\">", col + 1));
+            output.push_str(&render_nodes(state, object, block_contents)?);
+            output.push_str("</td>");
+
+            if state.interrupt.take() == Some(Interrupt::Break) {
+                break;
+            }
+
+            if col_last && !last {
+                output.push_str(&format!("</tr>\n<tr class=\"row{}\">", row + 2));
+            }
+        }
+
+        output.push_str("</tr>\n");
+        Ok(output)
+    };
+
+    let output = state.scopes.with_frame(Object::new(), render_rows)?;
+    Ok(Liquid::safe(output))
+}
+
+/// Items a loop iterates: array items, `[key, value]` pairs of an object
+/// sorted by key, or a single truthy value.
+fn loop_items(collection: &Liquid) -> Vec<Liquid> {
+    if let Some(array) = collection.as_array() {
+        array.iter().cloned().collect()
+    } else if let Some(map) = collection.as_object() {
+        let mut keys: Vec<&String> = map.keys().collect();
+        keys.sort();
+        keys.into_iter()
+            .map(|k| Arc::new(vec![k.as_str().into(), map[k].clone()]).into())
+            .collect()
+    } else if collection.is() {
+        vec![collection.clone()]
+    } else {
+        Vec::new()
+    }
+}
+
+fn for_loop_object(index: usize, length: usize, parent_loop: Option<&Liquid>) -> Liquid {
+    let mut for_loop = Object::new();
+    for_loop.insert("index".to_string(), (index as i64 + 1).into());
+    for_loop.insert("index0".to_string(), (index as i64).into());
+    for_loop.insert("rindex".to_string(), ((length - index) as i64).into());
+    for_loop.insert("rindex0".to_string(), ((length - index - 1) as i64).into());
+    for_loop.insert("first".to_string(), (index == 0).into());
+    for_loop.insert("last".to_string(), (index + 1 == length).into());
+    for_loop.insert("length".to_string(), (length as i64).into());
+    if let Some(parent_loop) = parent_loop {
+        for_loop.insert("parentloop".to_string(), parent_loop.clone());
+    }
+
+    Arc::new(for_loop).into()
+}
+
+fn loop_option_value(
+    state: &LiquidState,
+    object: &Liquid,
+    value: &str,
+) -> Result<usize, ParseError> {
+    let value = state.get_value(object, value)?.as_int().result(state)?;
+
+    Ok(value.max(0) as usize)
+}
+
+fn pfn_dbg(s: &LiquidState, prop: &Liquid, _: &FilterArguments) -> LiquidResult {
+    pfn_dbg_fn(s, prop)
 }
 
 fn pfn_len(_state: &LiquidState, liq: &Liquid) -> LiquidResult {
@@ -248,27 +1028,401 @@ fn pfn_content(state: &LiquidState, page: &Liquid) -> LiquidResult {
         file_path: path,
         ..*state
     };
-    Ok(markdown(&parse(&new_state, &object, &raw_content, offset as usize)?).into())
+    Ok(Liquid::safe(markdown(&parse(
+        &new_state,
+        &object,
+        raw_content,
+        offset as usize,
+    )?)))
 }
 
 fn pfn_dbg_fn(_state: &LiquidState, object: &Liquid) -> LiquidResult {
-    let object2: Liquid = match object.inner.mut_ref() {
-        LiquidInner::WeakObject(v) => {
-            if let Some(v) = v.upgrade() {
-                MutRc(v, false).into()
-            } else {
-                object.clone()
-            }
-        }
-        LiquidInner::WeakArray(v) => {
-            if let Some(v) = v.upgrade() {
-                MutRc(v, false).into()
-            } else {
-                object.clone()
-            }
-        }
+    let object2: Liquid = match &object.inner {
+        LiquidInner::WeakObject(v) => match v.upgrade() {
+            Some(v) => v.into(),
+            None => object.clone(),
+        },
+        LiquidInner::WeakArray(v) => match v.upgrade() {
+            Some(v) => v.into(),
+            None => object.clone(),
+        },
         o => o.clone().into(),
     };
 
     Ok((format!("{:#?}", object2)).into())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use yaml_rust::YamlLoader;
+
+    use super::*;
+    use crate::{
+        config::Config,
+        parser::yaml_to_liquid,
+        report::BuildReport,
+        template::{BlockOverrides, Templates},
+    };
+
+    /// Renders `source` as `site/test.html` for a page with the front matter `page`.
+    fn try_render(source: &str, page: &str) -> Result<String, ParseError> {
+        try_render_with(&[], source, page)
+    }
+
+    /// Like `try_render`, with the templates `files` as `(path, source)`.
+    fn try_render_with(
+        files: &[(&str, &str)],
+        source: &str,
+        page: &str,
+    ) -> Result<String, ParseError> {
+        let config: Config = serde_yaml::from_str("output: out\ntheme: default").unwrap();
+        let page = match YamlLoader::load_from_str(page).unwrap().pop() {
+            Some(yaml) => yaml_to_liquid(yaml, config.time_zone()),
+            None => Liquid::default_object(),
+        };
+
+        let state = LiquidState {
+            plugins: &Plugins::new(),
+            file_path: "site/test.html",
+            page_path: "site/test.html",
+            current_line: 0,
+            current_column: 0,
+            interrupt: &Cell::new(None),
+            templates: &Templates::default(),
+            config: &config,
+            scopes: &Scopes::default(),
+            blocks: &BlockOverrides::default(),
+            report: &BuildReport::deferred(),
+            trace: &RefCell::default(),
+        };
+
+        for (path, file) in files {
+            state.templates.insert(&state, Path::new(path), file)?;
+        }
+
+        parse(&state, &page, source, 0)
+    }
+
+    fn render(source: &str, page: &str) -> String {
+        render_with(&[], source, page)
+    }
+
+    fn render_with(files: &[(&str, &str)], source: &str, page: &str) -> String {
+        try_render_with(files, source, page)
+            .unwrap_or_else(|e| panic!("{source:?} failed, {}", e.message))
+    }
+
+    #[test]
+    fn unordered_comparisons_are_false() {
+        let source = "{% if page.missing OP 5 %}yes{% else %}no{% endif %}";
+        for operator in ["<", ">", "<=", ">="] {
+            assert_eq!(render(&source.replace("OP", operator), ""), "no");
+        }
+
+        assert_eq!(
+            render("{% if page.missing != 5 %}yes{% endif %}", ""),
+            "yes"
+        );
+        assert_eq!(
+            render("{% if 'a' < 5 %}yes{% else %}no{% endif %}", ""),
+            "no"
+        );
+        assert_eq!(render("{% if 4 < 5 %}yes{% endif %}", ""), "yes");
+        assert!(try_render("{% if 4 =< 5 %}yes{% endif %}", "").is_err());
+    }
+
+    #[test]
+    fn forloop_fields() {
+        let page = "items: [a, b, c]";
+
+        assert_eq!(
+            render(
+                "{% for i in page.items %}{{ i }}{{ forloop.index }}{{ forloop.index0 }}\
+                 {{ forloop.rindex }}{{ forloop.rindex0 }}{{ forloop.length }}\
+                 {% if forloop.first %}F{% endif %}{% if forloop.last %}L{% endif %} {% endfor %}",
+                page
+            ),
+            "a10323F b21213 c32103L "
+        );
+        assert_eq!(
+            render(
+                "{% for a in (1..2) %}{% for b in (1..2) %}\
+                 {{ forloop.parentloop.index }}{{ forloop.index }} {% endfor %}{% endfor %}",
+                ""
+            ),
+            "11 12 21 22 "
+        );
+    }
+
+    #[test]
+    fn for_options_and_else() {
+        assert_eq!(
+            render(
+                "{% for i in (1..6) limit: 2 offset: 1 %}{{ i }}{% endfor %}",
+                ""
+            ),
+            "23"
+        );
+        assert_eq!(
+            render("{% for i in (1..3) reversed %}{{ i }}{% endfor %}", ""),
+            "321"
+        );
+        assert_eq!(
+            render(
+                "{% for i in page.none %}{{ i }}{% else %}empty{% endfor %}",
+                ""
+            ),
+            "empty"
+        );
+    }
+
+    #[test]
+    fn break_and_continue() {
+        assert_eq!(
+            render(
+                "{% for i in (1..6) %}{% if i == 2 %}{% continue %}{% endif %}\
+                 {% if i == 5 %}{% break %}{% endif %}{{ i }}{% endfor %}",
+                ""
+            ),
+            "134"
+        );
+        assert_eq!(
+            render(
+                "{% for a in (1..2) %}{% for b in (1..3) %}{% if b == 2 %}{% break %}{% endif %}\
+                 {{ a }}{{ b }} {% endfor %}{% endfor %}",
+                ""
+            ),
+            "11 21 "
+        );
+    }
+
+    #[test]
+    fn if_elsif_else() {
+        let source = "{% if page.n == 1 %}one{% elsif page.n == 2 %}two\
+                      {% elsif page.n > 2 and page.n < 5 %}few{% else %}many{% endif %}";
+
+        assert_eq!(render(source, "n: 1"), "one");
+        assert_eq!(render(source, "n: 2"), "two");
+        assert_eq!(render(source, "n: 4"), "few");
+        assert_eq!(render(source, "n: 9"), "many");
+        assert_eq!(
+            render(
+                "{% if false or true and false %}y{% else %}n{% endif %}",
+                ""
+            ),
+            "n"
+        );
+        assert_eq!(
+            render(
+                "{% if page.s == empty and page.b == blank %}y{% endif %}",
+                "s: ''\nb: ' '"
+            ),
+            "y"
+        );
+    }
+
+    #[test]
+    fn unless() {
+        let source = "{% unless page.draft %}published{% else %}draft{% endunless %}";
+
+        assert_eq!(render(source, "draft: false"), "published");
+        assert_eq!(render(source, "draft: true"), "draft");
+        assert_eq!(render(source, ""), "published");
+    }
+
+    #[test]
+    fn contains() {
+        let page = "title: Hello world\ntags: [rust, liquid]\nmeta: { lang: en }";
+        let source = "{% if page.title contains 'world' %}s{% endif %}\
+                      {% if page.tags contains 'rust' %}a{% endif %}\
+                      {% if page.tags contains 'go' %}x{% endif %}\
+                      {% if page.meta contains 'lang' %}o{% endif %}\
+                      {% if page.missing contains 'a' %}x{% endif %}";
+
+        assert_eq!(render(source, page), "sao");
+    }
+
+    #[test]
+    fn case_when_with_several_values() {
+        let source = "{% case page.size %}ignored{% when 'S', 'M' %}small\
+                      {% when 'L' or 'XL' %}large{% when 1 %}one{% else %}other{% endcase %}";
+
+        assert_eq!(render(source, "size: M"), "small");
+        assert_eq!(render(source, "size: XL"), "large");
+        assert_eq!(render(source, "size: 1"), "one");
+        assert_eq!(render(source, "size: XS"), "other");
+        assert_eq!(
+            render(
+                "{% case 'a, b' %}{% when 'a, b' %}quoted{% when 'a' %}a{% endcase %}",
+                ""
+            ),
+            "quoted"
+        );
+        assert_eq!(
+            render(
+                "{% case 2 %}{% when 2 %}x{% when 1, 2 %}y{% else %}z{% endcase %}",
+                ""
+            ),
+            "xy"
+        );
+    }
+
+    #[test]
+    fn missing_includes_are_errors() {
+        let had_includes = Path::new("includes").exists();
+
+        let error = try_render("{% include missing.html %}", "").unwrap_err();
+        assert!(error
+            .message
+            .contains("includes/missing.html could not be read"));
+        assert_eq!(Path::new("includes").exists(), had_includes);
+    }
+
+    #[test]
+    fn include_parameters_and_variables() {
+        let files = [(
+            "includes/card.html",
+            "[{{ include.title }}|{{ page.title }}|{{ color }}]{% assign seen = true %}",
+        )];
+        let source = "{% assign color = 'red' %}\
+                      {% include card.html title: 'Hi' %}{{ include.title }}|{{ seen }}";
+
+        assert_eq!(
+            render_with(&files, source, "title: Page"),
+            "[Hi|Page|red]|true"
+        );
+        assert_eq!(
+            render_with(
+                &files,
+                "{% include card.html title: page.title %}",
+                "title: Page"
+            ),
+            "[Page|Page|]"
+        );
+    }
+
+    #[test]
+    fn assign_does_not_change_the_page() {
+        let source = "{% assign title = 'new' %}{{ title }}|{{ page.title }}\
+                      {% capture title %}captured{% endcapture %}|{{ title }}|{{ page.title }}";
+        assert_eq!(render(source, "title: Old"), "new|Old|captured|Old");
+
+        let source = "{% for i in (1..2) %}{% assign last = i %}{% endfor %}{{ last }}|{{ i }}";
+        assert_eq!(render(source, ""), "2|");
+    }
+
+    #[test]
+    fn render_only_sees_its_parameters() {
+        let files = [
+            (
+                "includes/card.html",
+                "[{{ title }}|{{ color }}|{{ page.title }}]{% assign color = 'blue' %}",
+            ),
+            (
+                "includes/item.html",
+                "{{ item }}{{ product }}{{ forloop.index }},",
+            ),
+        ];
+
+        let source =
+            "{% assign color = 'red' %}{% render 'card.html', title: page.title %}{{ color }}";
+        assert_eq!(render_with(&files, source, "title: Page"), "[Page||]red");
+
+        let source = "{% render 'card.html' with page.title as title %}";
+        assert_eq!(render_with(&files, source, "title: Page"), "[Page||]");
+
+        let source =
+            "{% render 'item.html' with page.title %}|{% render 'item.html' for page.items %}";
+        assert_eq!(
+            render_with(&files, source, "title: T\nitems: [a, b]"),
+            "T,|a1,b2,"
+        );
+
+        let source = "{% render 'item.html' for page.items as product %}";
+        assert_eq!(render_with(&files, source, "items: [a, b]"), "a1,b2,");
+    }
+
+    #[test]
+    fn blocks_override_and_call_super() {
+        let files = [
+            (
+                "layouts/base.html",
+                "<nav>{% block nav %}home{% endblock %}</nav>\
+                 <main>{% block main %}empty{% endblock %}</main>",
+            ),
+            (
+                "layouts/docs.html",
+                "{% extends 'base.html' %}{% block nav %}{{ block.super }}, docs{% endblock %}",
+            ),
+            (
+                "includes/page.html",
+                "{% extends 'docs.html' %}ignored\
+                 {% block nav %}{{ block.super }}, {{ page.title }}{% endblock %}\
+                 {% block main %}{{ block.name }}{% endblock %}",
+            ),
+        ];
+
+        assert_eq!(
+            render_with(&files, "{% include page.html %}", "title: Intro"),
+            "<nav>home, docs, Intro</nav><main>main</main>"
+        );
+    }
+
+    #[test]
+    fn extends_cycles_are_errors() {
+        let files = [
+            ("layouts/a.html", "{% extends 'b.html' %}"),
+            ("layouts/b.html", "{% extends 'a.html' %}"),
+            ("includes/a.html", "{% extends 'a.html' %}"),
+        ];
+
+        let error = try_render_with(&files, "{% include a.html %}", "").unwrap_err();
+        assert!(
+            error.message.starts_with("Template cycle"),
+            "{}",
+            error.message
+        );
+    }
+
+    #[test]
+    fn cycle_groups() {
+        let source = "{% for i in (1..4) %}{% cycle 'a', 'b', 'c' %}\
+                      {% cycle 'rows': 'a', 'b', 'c' %}{% cycle 'rows': 'a', 'b', 'c' %},{% endfor %}";
+        assert_eq!(render(source, ""), "aab,bca,cbc,aab,");
+    }
+
+    #[test]
+    fn counters_are_independent_of_assign() {
+        let source = "{% increment n %}{% increment n %}{% decrement m %}{% decrement m %}\
+                      {% assign n = 'x' %}{{ n }}{% increment n %}{{ m }}";
+        assert_eq!(render(source, ""), "01-1-2x2-2");
+    }
+
+    #[test]
+    fn liquid_tag_and_echo() {
+        let source = "{% liquid\n  # a comment\n  assign name = page.title | upcase\n\
+                      if name\n    echo name | append: '!'\n  endif\n%}";
+        assert_eq!(render(source, "title: hi"), "HI!");
+    }
+
+    #[test]
+    fn tablerow_cols_limit_and_offset() {
+        let source = "{% tablerow n in (1..6) cols: 2 limit: 4 offset: 1 %}\
+                      {{ n }}{{ tablerowloop.col }}{{ tablerowloop.row }}{% endtablerow %}";
+        assert_eq!(
+            render(source, ""),
+            "<tr class=\"row1\">\n<td class=\"col1\">211</td><td class=\"col2\">321</td></tr>\n\
+             <tr class=\"row2\"><td class=\"col1\">412</td><td class=\"col2\">522</td></tr>\n"
+        );
+
+        assert_eq!(
+            render(
+                "{% tablerow n in page.items %}{{ n }}{% endtablerow %}",
+                "items: []"
+            ),
+            "<tr class=\"row1\">\n</tr>\n"
+        );
+    }
+}
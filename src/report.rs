@@ -0,0 +1,226 @@
+//! Errors and warnings of a build, collected so every broken page is reported
+//! and summarised instead of only the first one.
+
+use std::{
+    fmt,
+    path::Path,
+    sync::{Mutex, MutexGuard},
+};
+
+use crate::{diagnostic, Error};
+
+/// The part of the build a problem comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Config,
+    Page,
+    Layout,
+    Asset,
+    /// Clearing or writing the `out` folder.
+    Output,
+}
+
+impl Stage {
+    /// The stage of the file at `path`, by the folder it is in.
+    pub fn of_path(path: &str) -> Self {
+        let path = Path::new(path);
+
+        if path.starts_with("layouts") {
+            Stage::Layout
+        } else if path.starts_with("assets") || path.starts_with("out/assets") {
+            Stage::Asset
+        } else if path.starts_with("out") {
+            Stage::Output
+        } else if path.ends_with("config.yaml") {
+            Stage::Config
+        } else {
+            Stage::Page
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Stage::Config => "config",
+            Stage::Page => "page",
+            Stage::Layout => "layout",
+            Stage::Asset => "asset",
+            Stage::Output => "output",
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Warning {
+    pub stage: Stage,
+    pub path: String,
+    pub message: String,
+}
+
+#[derive(Debug)]
+enum Problem {
+    Error(Error),
+    Warning(Warning),
+}
+
+impl Problem {
+    fn print(&self) {
+        match self {
+            Problem::Error(error) => diagnostic::print_error(error),
+            Problem::Warning(w) => diagnostic::print_warning(&w.message, &w.path),
+        }
+    }
+}
+
+/// Problems are printed as they happen and summarised by `print_summary`.
+///
+/// A deferred report only collects them, render threads use one per page and
+/// `append` them in page order so the output does not depend on scheduling.
+#[derive(Debug, Default)]
+pub struct BuildReport {
+    problems: Mutex<Vec<Problem>>,
+    deferred: bool,
+}
+
+impl BuildReport {
+    pub fn deferred() -> Self {
+        Self {
+            deferred: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn error(&self, error: Error) {
+        self.add(Problem::Error(error));
+    }
+
+    pub fn warning(&self, stage: Stage, path: &str, message: &str) {
+        self.add(Problem::Warning(Warning {
+            stage,
+            path: path.to_string(),
+            message: message.to_string(),
+        }));
+    }
+
+    /// Adds every problem of `other`, printing them unless this report is
+    /// deferred too.
+    pub fn append(&self, other: BuildReport) {
+        let problems = other
+            .problems
+            .into_inner()
+            .unwrap_or_else(|e| e.into_inner());
+
+        for problem in problems {
+            self.add(problem);
+        }
+    }
+
+    fn add(&self, problem: Problem) {
+        if !self.deferred {
+            problem.print();
+        }
+
+        self.problems().push(problem);
+    }
+
+    fn problems(&self) -> MutexGuard<'_, Vec<Problem>> {
+        self.problems.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The file of every problem, in the order they were reported.
+    pub fn paths(&self) -> Vec<String> {
+        let problems = self.problems();
+        problems
+            .iter()
+            .map(|p| match p {
+                Problem::Error(error) => error.path(),
+                Problem::Warning(warning) => warning.path.clone(),
+            })
+            .collect()
+    }
+
+    pub fn error_count(&self) -> usize {
+        let problems = self.problems();
+        problems
+            .iter()
+            .filter(|p| matches!(p, Problem::Error(_)))
+            .count()
+    }
+
+    pub fn warning_count(&self) -> usize {
+        let problems = self.problems();
+        problems
+            .iter()
+            .filter(|p| matches!(p, Problem::Warning(_)))
+            .count()
+    }
+
+    /// Whether the build should exit with a failure.
+    pub fn failed(&self, fail_on_warnings: bool) -> bool {
+        self.error_count() > 0 || (fail_on_warnings && self.warning_count() > 0)
+    }
+
+    /// ```text
+    /// Build finished with 1 error and 1 warning.
+    ///
+    /// severity  stage   file             message
+    /// warning   config  config.yaml      timezone "Mars/Olympus" is not a time zone ...
+    /// error     layout  site/index.html  Filter upcas does not exists.
+    /// ```
+    pub fn print_summary(&self) {
+        let (errors, warnings) = (self.error_count(), self.warning_count());
+        if errors == 0 && warnings == 0 {
+            return;
+        }
+
+        let plural = |count: usize, word: &str| match count {
+            1 => format!("1 {word}"),
+            count => format!("{count} {word}s"),
+        };
+        eprintln!(
+            "Build finished with {} and {}.\n",
+            plural(errors, "error"),
+            plural(warnings, "warning")
+        );
+
+        let problems = self.problems();
+        let warning_rows = problems.iter().filter_map(|p| match p {
+            Problem::Warning(w) => Some([
+                "warning".to_string(),
+                w.stage.to_string(),
+                w.path.clone(),
+                w.message.lines().next().unwrap_or_default().to_string(),
+            ]),
+            Problem::Error(_) => None,
+        });
+        let error_rows = problems.iter().filter_map(|p| match p {
+            Problem::Error(e) => Some([
+                "error".to_string(),
+                e.stage().to_string(),
+                e.path(),
+                e.to_string().lines().next().unwrap_or_default().to_string(),
+            ]),
+            Problem::Warning(_) => None,
+        });
+        let rows: Vec<[String; 4]> = warning_rows.chain(error_rows).collect();
+
+        let header = ["severity", "stage", "file", "message"].map(String::from);
+        let mut widths = header.clone().map(|h| h.len());
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        for row in [header].iter().chain(&rows) {
+            let [severity, stage, path, message] = row;
+            eprintln!(
+                "{severity:<w0$}  {stage:<w1$}  {path:<w2$}  {message}",
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2],
+            );
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use std::{cell::RefCell, collections::HashMap};
+
+use crate::liquid::{Liquid, Object};
+
+/// Variables of a render that do not belong to the page.
+///
+/// The first frame is global and holds `assign` and `capture` results, loops
+/// and includes push their own frames on top. Lookups go from the innermost
+/// frame out and fall back to the page object, which is never written to.
+#[derive(Debug)]
+pub struct Scopes {
+    frames: RefCell<Vec<Object>>,
+    /// `increment`/`decrement` counters, looked up after every frame so
+    /// `assign` shadows them without changing them.
+    counters: RefCell<HashMap<String, i64>>,
+    /// Position of every `cycle` group.
+    cycles: RefCell<HashMap<String, usize>>,
+}
+
+impl Default for Scopes {
+    fn default() -> Self {
+        Self::new(Object::new())
+    }
+}
+
+impl Scopes {
+    /// Scopes of an isolated render whose global frame is `global`.
+    pub fn new(global: Object) -> Self {
+        Self {
+            frames: RefCell::new(vec![global]),
+            counters: Default::default(),
+            cycles: Default::default(),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Liquid> {
+        self.frames
+            .borrow()
+            .iter()
+            .rev()
+            .find_map(|frame| frame.get(name).cloned())
+            .or_else(|| self.counters.borrow().get(name).map(|&c| c.into()))
+    }
+
+    /// Returns the counter `name` and then increments it, counters start at 0.
+    pub fn increment(&self, name: &str) -> i64 {
+        let mut counters = self.counters.borrow_mut();
+        let counter = counters.entry(name.to_string()).or_default();
+
+        *counter += 1;
+        *counter - 1
+    }
+
+    /// Decrements the counter `name` and then returns it.
+    pub fn decrement(&self, name: &str) -> i64 {
+        let mut counters = self.counters.borrow_mut();
+        let counter = counters.entry(name.to_string()).or_default();
+
+        *counter -= 1;
+        *counter
+    }
+
+    /// Index of the next value of the `cycle` group with `len` values.
+    pub fn cycle(&self, group: &str, len: usize) -> usize {
+        let mut cycles = self.cycles.borrow_mut();
+        let position = cycles.entry(group.to_string()).or_default();
+
+        let index = *position % len.max(1);
+        *position = index + 1;
+        index
+    }
+
+    /// Sets a variable of the global frame, like `assign`.
+    pub fn assign(&self, name: &str, value: Liquid) {
+        self.frames.borrow_mut()[0].insert(name.to_string(), value);
+    }
+
+    /// Sets a variable of the innermost frame, like a loop variable.
+    pub fn set(&self, name: &str, value: Liquid) {
+        let mut frames = self.frames.borrow_mut();
+        let frame = frames
+            .last_mut()
+            .expect("The global frame is never popped.");
+
+        frame.insert(name.to_string(), value);
+    }
+
+    /// Runs `f` with `frame` pushed on top, the frame is popped afterwards.
+    pub fn with_frame<T>(&self, frame: Object, f: impl FnOnce() -> T) -> T {
+        self.frames.borrow_mut().push(frame);
+        let result = f();
+        self.frames.borrow_mut().pop();
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is(scopes: &Scopes, name: &str, value: impl Into<Liquid>) -> bool {
+        scopes.get(name) == Some(value.into())
+    }
+
+    #[test]
+    fn frames_are_popped_and_assign_is_global() {
+        let scopes = Scopes::default();
+        scopes.assign("title", "global".into());
+
+        scopes.with_frame(Object::new(), || {
+            scopes.set("item", 1.into());
+            scopes.set("title", "loop".into());
+            scopes.assign("total", 2.into());
+
+            assert!(is(&scopes, "title", "loop"));
+            assert!(is(&scopes, "item", 1));
+        });
+
+        assert!(is(&scopes, "title", "global"));
+        assert!(scopes.get("item").is_none());
+        assert!(is(&scopes, "total", 2));
+    }
+
+    #[test]
+    fn assign_shadows_counters() {
+        let scopes = Scopes::default();
+        assert_eq!(scopes.increment("n"), 0);
+        assert!(is(&scopes, "n", 1));
+
+        scopes.assign("n", "text".into());
+        assert!(is(&scopes, "n", "text"));
+        assert_eq!(scopes.increment("n"), 1);
+    }
+}
@@ -0,0 +1,213 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::{
+    ast::{self, Node},
+    lexer::{self, Location},
+    liquid::Liquid,
+    parser::{get_layout, parse_front_matter, render_nodes, LiquidState, ParseError},
+};
+
+/// A parsed template, compiled once and rendered for any number of objects.
+#[derive(Debug)]
+pub struct Template {
+    pub path: String,
+    pub nodes: Vec<Node>,
+    /// Front matter of layouts and includes, an empty object without one.
+    pub front_matter: Liquid,
+}
+
+impl Template {
+    /// Compiles `source` as the file `state.file_path`, skipping its first
+    /// `skip_offset` bytes (the front matter).
+    pub fn compile(
+        state: &LiquidState,
+        source: &str,
+        skip_offset: usize,
+    ) -> Result<Self, ParseError> {
+        let tokens = lexer::tokenize(state, source, skip_offset)?;
+
+        Ok(Self {
+            path: state.file_path.to_string(),
+            nodes: ast::build(state, tokens)?,
+            front_matter: Liquid::default_object(),
+        })
+    }
+
+    /// Renders the template, or with `{% extends "base.html" %}` renders the
+    /// base layout with the `block`s of this template overriding its own.
+    pub fn render(&self, state: &LiquidState, object: &Liquid) -> Result<String, ParseError> {
+        let state = LiquidState {
+            file_path: &self.path,
+            ..*state
+        };
+
+        let (extends, location) = match self.extends() {
+            Some(extends) => extends,
+            None => return render_nodes(&state, object, &self.nodes),
+        };
+
+        let render_base = || -> Result<String, ParseError> {
+            let base_name = state.get_value(object, extends)?.to_output();
+
+            if state.blocks.chain.contains(&self.path.as_str()) {
+                let mut cycle: Vec<String> = state
+                    .blocks
+                    .chain
+                    .iter()
+                    .map(|p| format!("`{p}`"))
+                    .collect();
+                cycle.push(format!("`{}`", self.path));
+                return Err(format!("Template cycle {}.", cycle.join(" extends ")).into());
+            }
+
+            let mut blocks = state.blocks.clone();
+            blocks.chain.push(&self.path);
+            collect_blocks(&self.path, &self.nodes, &mut blocks);
+
+            let base = get_layout(&state, &base_name)?;
+
+            base.render(
+                &LiquidState {
+                    blocks: &blocks,
+                    ..state
+                },
+                object,
+            )
+        };
+
+        render_base().map_err(|e| e.add_stack(&state, location))
+    }
+
+    /// The markup after `extends` of a top level `{% extends %}` tag.
+    fn extends(&self) -> Option<(&str, &Location)> {
+        self.nodes.iter().find_map(|node| match node {
+            Node::Tag {
+                name,
+                markup,
+                location,
+            } if name == EXTENDS => Some((markup[EXTENDS.len()..].trim(), location)),
+            _ => None,
+        })
+    }
+}
+
+const EXTENDS: &str = "extends";
+const BLOCK: &str = "block";
+
+/// `{% block name %}` overrides by name, most derived first.
+#[derive(Debug, Default, Clone)]
+pub struct BlockOverrides<'n> {
+    pub blocks: HashMap<&'n str, Vec<BlockOverride<'n>>>,
+    /// Paths of the templates extending the current one, to detect cycles.
+    pub chain: Vec<&'n str>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BlockOverride<'n> {
+    pub path: &'n str,
+    pub nodes: &'n [Node],
+}
+
+/// Adds every `block` of `nodes`, nested ones included, after the overrides
+/// of more derived templates.
+fn collect_blocks<'n>(path: &'n str, nodes: &'n [Node], blocks: &mut BlockOverrides<'n>) {
+    for node in nodes {
+        if let Node::Block {
+            name,
+            markup,
+            children,
+            ..
+        } = node
+        {
+            if name == BLOCK {
+                let block_name = markup[BLOCK.len()..].trim();
+                blocks
+                    .blocks
+                    .entry(block_name)
+                    .or_default()
+                    .push(BlockOverride {
+                        path,
+                        nodes: children,
+                    });
+            }
+
+            collect_blocks(path, children, blocks);
+        }
+    }
+}
+
+/// Compiled layouts and includes of a build, keyed by path and shared by
+/// every render thread.
+#[derive(Debug, Default)]
+pub struct Templates {
+    cache: Mutex<HashMap<PathBuf, Arc<Template>>>,
+}
+
+impl Templates {
+    /// Reads and compiles `path` the first time it is requested. Threads
+    /// asking for it at the same time may both compile it, the first one is
+    /// kept.
+    pub fn get(&self, state: &LiquidState, path: &Path) -> Result<Arc<Template>, ParseError> {
+        if let Some(template) = self.cache().get(path) {
+            return Ok(template.clone());
+        }
+
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => return Err(format!("{} could not be read. {e}", path.display()).into()),
+        };
+
+        self.insert(state, path, &source)
+    }
+
+    /// Compiles `source` as the template `path`, unless a thread compiled it
+    /// first.
+    pub fn insert(
+        &self,
+        state: &LiquidState,
+        path: &Path,
+        source: &str,
+    ) -> Result<Arc<Template>, ParseError> {
+        let file_path = path.to_string_lossy();
+        let compile_state = LiquidState {
+            file_path: &file_path,
+            current_line: 0,
+            current_column: 0,
+            ..*state
+        };
+
+        let (front_matter, skip_offset) = match source.starts_with("---") {
+            true => match parse_front_matter(source, state.config.time_zone()) {
+                Ok((front_matter, 0)) => (front_matter, 0),
+                Ok((front_matter, offset)) => (front_matter, offset + 3),
+                Err(e) => {
+                    return Err(format!("{} has invalid front matter. {e}", path.display()).into())
+                }
+            },
+            false => (Liquid::default_object(), 0),
+        };
+
+        let template = Arc::new(Template {
+            front_matter,
+            ..Template::compile(&compile_state, source, skip_offset)?
+        });
+
+        let template = self
+            .cache()
+            .entry(path.to_path_buf())
+            .or_insert(template)
+            .clone();
+
+        Ok(template)
+    }
+
+    fn cache(&self) -> MutexGuard<'_, HashMap<PathBuf, Arc<Template>>> {
+        // A thread that panicked while holding the lock left the map intact.
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}